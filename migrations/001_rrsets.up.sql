@@ -0,0 +1,18 @@
+-- Allow multiple records with different data in the same RRset
+CREATE TABLE IF NOT EXISTS records_new (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	zoneid INTEGER NOT NULL,
+	subdomain TEXT NOT NULL,
+	ttl INTEGER NOT NULL,
+	class TEXT NOT NULL,
+	type TEXT NOT NULL,
+	data TEXT NOT NULL,
+	FOREIGN KEY(zoneid) REFERENCES zones(id),
+	UNIQUE (zoneid, subdomain, class, type, data),
+	CHECK (ttl >= 0 AND ttl <= 4294967295)
+) STRICT;
+INSERT INTO records_new (id, zoneid, subdomain, ttl, class, type, data)
+SELECT id, zoneid, subdomain, ttl, class, type, data FROM records;
+DROP TABLE records;
+ALTER TABLE records_new RENAME TO records;
+CREATE INDEX records_index ON records(zoneid, subdomain, class, type, ttl, data);
@@ -0,0 +1 @@
+-- Quoted character strings are also valid in the old format, so there is nothing to undo
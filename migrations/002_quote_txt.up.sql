@@ -0,0 +1,3 @@
+-- TXT and SPF data is now always stored as quoted character strings, quotes inside of it have to be escaped
+UPDATE OR REPLACE records SET data = '"' || replace(data, '"', '\"') || '"'
+WHERE type IN ('TXT', 'SPF') AND data NOT LIKE '"%';
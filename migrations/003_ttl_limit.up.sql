@@ -0,0 +1,19 @@
+-- TTLs must not be larger than 2^31 - 1 (RFC 2181 section 8), larger values are lowered to that limit
+-- instead of being treated as zero like RFC 2181 suggests, so the records are still cached
+CREATE TABLE IF NOT EXISTS records_new (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	zoneid INTEGER NOT NULL,
+	subdomain TEXT NOT NULL,
+	ttl INTEGER NOT NULL,
+	class TEXT NOT NULL,
+	type TEXT NOT NULL,
+	data TEXT NOT NULL,
+	FOREIGN KEY(zoneid) REFERENCES zones(id),
+	UNIQUE (zoneid, subdomain, class, type, data),
+	CHECK (ttl >= 0 AND ttl <= 2147483647)
+) STRICT;
+INSERT INTO records_new (id, zoneid, subdomain, ttl, class, type, data)
+SELECT id, zoneid, subdomain, MIN(ttl, 2147483647), class, type, data FROM records;
+DROP TABLE records;
+ALTER TABLE records_new RENAME TO records;
+CREATE INDEX records_index ON records(zoneid, subdomain, class, type, ttl, data);
@@ -0,0 +1,2 @@
+-- The addresses stay in their canonical form, which older versions can read as well
+DROP TABLE pending_upgrades;
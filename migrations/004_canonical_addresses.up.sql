@@ -0,0 +1,6 @@
+-- A and AAAA data has to be rewritten in its canonical form once, which cannot be done in SQL.
+-- The rewrite happens after the migrations and removes the row, so that it is only done once.
+CREATE TABLE IF NOT EXISTS pending_upgrades (
+	name TEXT PRIMARY KEY NOT NULL
+) STRICT;
+INSERT INTO pending_upgrades (name) VALUES ('canonical_addresses');
@@ -12,11 +12,12 @@ rec {
   craneLib = crane.mkLib pkgs;
 
   sqlFilter = path: _type: null != builtins.match ".*sql$" path;
-  sqlOrCargo = path: type: (sqlFilter path type) || (craneLib.filterCargoSources path type);
+  pslFilter = path: _type: null != builtins.match ".*public_suffix_list\\.dat$" path;
+  sqlOrPslOrCargo = path: type: (sqlFilter path type) || (pslFilter path type) || (craneLib.filterCargoSources path type);
 
   src = lib.cleanSourceWith {
     src = craneLib.path ../.; # The original, unfiltered source
-    filter = sqlOrCargo;
+    filter = sqlOrPslOrCargo;
   };
 
   # Common arguments can be set here to avoid repeating them later
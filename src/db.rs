@@ -1,16 +1,21 @@
 // SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
 // SPDX-License-Identifier: GPL-3.0-only
 
+use crate::parse::{self, Condition};
+use crate::psl::PublicSuffixList;
+use crate::record::{RData, RecordType};
 use crate::zone_files;
-use color_eyre::eyre::{Result, WrapErr, eyre};
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
 use futures::StreamExt;
 use indoc::{formatdoc, indoc};
 use sqlx::{
-	Pool, Row, Sqlite, Transaction,
+	Connection, Pool, Row, Sqlite, SqliteConnection, Transaction,
 	sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
 };
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
-use tldextract::TldExtractor;
 
 #[derive(sqlx::FromRow)]
 struct Zone {
@@ -18,6 +23,16 @@ struct Zone {
 	name: String,
 }
 
+#[derive(sqlx::FromRow)]
+struct ZoneResourceRecord {
+	zone: String,
+	subdomain: String,
+	ttl: i64,
+	class: String,
+	type_: String,
+	data: String,
+}
+
 #[derive(sqlx::FromRow)]
 struct ResourceRecord {
 	subdomain: String,
@@ -40,30 +55,332 @@ pub async fn init(dir: &PathBuf) -> Result<Pool<Sqlite>> {
 		.await
 		.wrap_err_with(|| format!("Cannot open database file `{}`", db_file_path.display()))?;
 
+	setup(&pool).await?;
+
+	Ok(pool)
+}
+
+async fn setup(pool: &Pool<Sqlite>) -> Result<()> {
 	sqlx::migrate!("./migrations")
-		.run(&pool)
+		.run(pool)
 		.await
 		.wrap_err("Cannot run database migrations")?;
 
-	Ok(pool)
+	canonicalize_addresses(pool).await
 }
 
-fn tld_to_zone_and_subdomain(tld_ext: &TldExtractor, input: &str) -> Result<(String, String)> {
-	let tld = tld_ext.extract(input).wrap_err_with(|| {
-		format!("Cannot extract the TLD information from the provided domain name: {input}")
-	})?;
-	let domain = tld
-		.domain
-		.ok_or_else(|| eyre!("Cannot extract the domain from the provided domain name: {input}"))?;
-	let suffix = tld
-		.suffix
+/// Rewrite A and AAAA data that was stored before it was parsed into addresses, e.g. `2001:DB8:0:0::1`,
+/// in its canonical form (RFC 5952) so that it compares equal to newly added data.
+/// This cannot be done in an SQL migration, so the migration only records that it has to be done once.
+async fn canonicalize_addresses(pool: &Pool<Sqlite>) -> Result<()> {
+	let mut tx = pool.begin().await.wrap_err("Cannot begin transaction")?;
+
+	let pending = sqlx::query(indoc! {"
+		DELETE FROM pending_upgrades WHERE name = 'canonical_addresses';
+	"})
+	.execute(&mut *tx)
+	.await
+	.wrap_err("Cannot DELETE from pending_upgrades table")?
+	.rows_affected()
+		> 0;
+	if !pending {
+		return Ok(());
+	}
+
+	let rows: Vec<(i64, String, String)> = sqlx::query_as(indoc! {"
+		SELECT id, type, data FROM records WHERE type IN ('A', 'AAAA');
+	"})
+	.fetch_all(&mut *tx)
+	.await
+	.wrap_err("Cannot SELECT rows from records table")?;
+
+	for (id, type_, data) in rows {
+		let rdata = parse::stored_rdata(&type_, &data).or_else(|| {
+			(type_ == "A")
+				.then(|| legacy_ipv4(&data))
+				.flatten()
+				.map(RData::A)
+		});
+		let Some(rdata) = rdata else {
+			// Invalid rows are left alone and reported when saving the zones
+			continue;
+		};
+		let canonical = rdata.to_string();
+		if canonical == data {
+			continue;
+		}
+		// If the same address is also stored in its canonical form, the duplicate is removed
+		sqlx::query(indoc! {"
+			UPDATE OR REPLACE records SET data = ?2 WHERE id = ?1;
+		"})
+		.bind(id)
+		.bind(canonical)
+		.execute(&mut *tx)
+		.await
+		.wrap_err("Cannot UPDATE row in records table")?;
+	}
+
+	tx.commit().await.wrap_err("Cannot commit transaction")?;
+
+	Ok(())
+}
+
+/// IPv4 address with leading zeros, like `192.000.002.001`, which older versions accepted.
+/// The octets are read as decimal numbers, like most people would expect.
+fn legacy_ipv4(data: &str) -> Option<Ipv4Addr> {
+	let octets: Vec<u8> = data
+		.split('.')
+		.map(|octet| {
+			((1..=3).contains(&octet.len()) && octet.bytes().all(|b| b.is_ascii_digit()))
+				.then(|| octet.parse().ok())
+				.flatten()
+		})
+		.collect::<Option<_>>()?;
+	<[u8; 4]>::try_from(octets).ok().map(Ipv4Addr::from)
+}
+
+// Special-use domains (RFC 6761, RFC 8375) and the top-level domain reserved by ICANN for private use.
+// They are not in the public suffix list, so each of them is treated as a zone of its own.
+// Other private zones, e.g. below `lan`, have to be declared with --zone.
+const PRIVATE_ZONES: &[&str] = &["home.arpa", "internal", "example", "test"];
+
+fn guess_zone_and_subdomain(psl: &PublicSuffixList, input: &str) -> Result<(String, String)> {
+	let name = normalize_name(input);
+	let labels: Vec<&str> = name.split('.').collect();
+	let suffix_len = psl
+		.suffix_len(&labels)
 		.ok_or_else(|| eyre!("Cannot extract the suffix from the provided domain name: {input}"))?;
-	let zone = format!("{domain}.{suffix}");
-	let subdomain = tld.subdomain.unwrap_or_else(|| "@".to_string());
+	// The zone is the public suffix plus one more label
+	let zone_start = labels
+		.len()
+		.checked_sub(suffix_len + 1)
+		.ok_or_else(|| eyre!("Cannot extract the domain from the provided domain name: {input}"))?;
+	let zone = labels[zone_start..].join(".");
+	if zone.starts_with('*') {
+		bail!("The wildcard name {input} cannot be the name of a zone");
+	}
+	let subdomain = if zone_start == 0 {
+		"@".to_string()
+	} else {
+		labels[..zone_start].join(".")
+	};
 
 	Ok((zone, subdomain))
 }
 
+pub fn normalize_name(name: &str) -> String {
+	// Names are case-insensitive, so they are always stored in lowercase
+	name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase()
+}
+
+fn is_in_zone(zone: &str, name: &str) -> bool {
+	name == zone
+		|| name
+			.strip_suffix(zone)
+			.is_some_and(|rest| rest.ends_with('.'))
+}
+
+fn split_at_zone(zone: &str, input: &str) -> Result<(String, String)> {
+	let name = normalize_name(input);
+	if name == zone {
+		return Ok((zone.to_string(), "@".to_string()));
+	}
+	let subdomain = name
+		.strip_suffix(zone)
+		.and_then(|rest| rest.strip_suffix('.'))
+		.filter(|subdomain| !subdomain.is_empty())
+		.ok_or_else(|| eyre!("The name {input} is not inside of the zone {zone}"))?;
+
+	Ok((zone.to_string(), subdomain.to_string()))
+}
+
+async fn find_zone(tx: &mut Transaction<'_, Sqlite>, name: &str) -> Result<Option<String>> {
+	// Use the longest zone that is equal to the name or a parent of it.
+	// substr() is used instead of LIKE because LIKE treats an underscore in the zone name as a wildcard.
+	let zone_row = sqlx::query(indoc! {"
+		SELECT name FROM zones
+		WHERE name = ?1 OR substr(?1, -length(name) - 1) = '.' || name
+		ORDER BY length(name) DESC
+		LIMIT 1;
+	"})
+	.bind(name)
+	.fetch_optional(&mut **tx)
+	.await
+	.wrap_err("Cannot SELECT row from zones table")?;
+
+	zone_row
+		.map(|row| row.try_get("name"))
+		.transpose()
+		.wrap_err("Cannot get name from zones table")
+}
+
+/// Settings of a session that determine which zone a name belongs to
+pub struct Scope<'a> {
+	/// Public suffix list used to guess the zone of names outside of all known zones, `None` if guessing is disabled
+	pub psl: Option<&'a PublicSuffixList>,
+	/// Zone set with the `zone` command, names are split against it instead of looking for their zone
+	pub zone: Option<String>,
+	/// Origin set with the `origin` command, relative names are relative to it or else to the zone
+	pub origin: Option<String>,
+}
+
+impl Scope<'_> {
+	/// Turn the name into an absolute name without the trailing dot, like nsupdate does
+	pub fn absolute_name(&self, input: &str) -> Result<String> {
+		if input.ends_with('.') {
+			return Ok(normalize_name(input));
+		}
+		let origin = self
+			.origin
+			.as_deref()
+			.or(self.zone.as_deref())
+			.ok_or_else(|| {
+				eyre!("The name {input} is ambiguous because it is relative but neither an origin nor a zone is set. Add a trailing dot to make it absolute")
+			})?;
+		let name = if input == "@" {
+			origin.to_string()
+		} else if origin.is_empty() {
+			// The origin is the root zone
+			normalize_name(input)
+		} else {
+			format!("{}.{origin}", normalize_name(input))
+		};
+		if name.len() > parse::MAX_NAME_LENGTH {
+			bail!("The name {name} is longer than 255 bytes");
+		}
+		Ok(name)
+	}
+
+	/// Make the names inside of the data absolute with a trailing dot, so they don't depend on the origin of the zone file
+	pub fn absolute_data(&self, data: RData) -> Result<RData> {
+		data.try_map_names(|name| {
+			self.absolute_name(name)
+				.map(|absolute_name| format!("{absolute_name}."))
+		})
+	}
+}
+
+async fn zone_and_subdomain(
+	tx: &mut Transaction<'_, Sqlite>,
+	scope: &Scope<'_>,
+	input: &str,
+) -> Result<(String, String)> {
+	let name = scope.absolute_name(input)?;
+	// An explicitly set zone takes precedence over the known zones, then the private zones,
+	// which in turn take precedence over guessing the zone from the public suffix list
+	if let Some(zone) = &scope.zone {
+		return split_at_zone(zone, &name);
+	}
+	if let Some(zone) = find_zone(tx, &name).await? {
+		return split_at_zone(&zone, &name);
+	}
+	if let Some(zone) = PRIVATE_ZONES.iter().find(|zone| is_in_zone(zone, &name)) {
+		return split_at_zone(zone, &name);
+	}
+	let psl = scope.psl.ok_or_else(|| {
+		eyre!("The name {name} is not inside of any known zone and guessing the zone is disabled")
+	})?;
+	guess_zone_and_subdomain(psl, &name)
+}
+
+pub async fn declare_zones(pool: &Pool<Sqlite>, zones: &[String]) -> Result<()> {
+	for zone in zones {
+		// Names are looked up in their ASCII form, so the zones have to be in it too
+		let name = parse::parse_name(zone)
+			.ok_or_else(|| eyre!("The zone {zone} given with --zone is not a valid name"))?;
+		let name = normalize_name(&name);
+		let mut tx = pool.begin().await.wrap_err("Cannot begin transaction")?;
+		insert_zone(&mut tx, &name).await?;
+		tx.commit().await.wrap_err("Cannot commit transaction")?;
+	}
+
+	Ok(())
+}
+
+/// Id of the zone, which is created if it is not known yet.
+/// A new zone takes over the records below it, no matter whether it was declared with --zone, set with the `zone` command or guessed.
+async fn insert_zone(tx: &mut Transaction<'_, Sqlite>, zone: &str) -> Result<i64> {
+	let inserted = sqlx::query(indoc! {"
+		INSERT OR IGNORE INTO zones (name) VALUES (?1);
+	"})
+	.bind(zone)
+	.execute(&mut **tx)
+	.await
+	.wrap_err("Cannot INSERT row into zones table")?
+	.rows_affected()
+		> 0;
+	if inserted {
+		move_records_into_subzone(tx, zone).await?;
+	}
+	sqlx::query_scalar(indoc! {"
+		SELECT id FROM zones WHERE name = ?1;
+	"})
+	.bind(zone)
+	.fetch_one(&mut **tx)
+	.await
+	.wrap_err("Cannot SELECT row from zones table")
+}
+
+/// Records that were added to a parent zone before the subzone was declared belong to the subzone now.
+/// Otherwise they would stay in the zone file of the parent, where no update could ever reach them again.
+async fn move_records_into_subzone(tx: &mut Transaction<'_, Sqlite>, zone: &str) -> Result<()> {
+	let zones: Vec<Zone> = sqlx::query_as(indoc! {"
+		SELECT id, name FROM zones;
+	"})
+	.fetch_all(&mut **tx)
+	.await
+	.wrap_err("Cannot SELECT rows from zones table")?;
+	let zoneid = zones
+		.iter()
+		.find(|row| row.name == zone)
+		.map(|row| row.id)
+		.ok_or_else(|| eyre!("The zone {zone} was not inserted"))?;
+
+	for parent in zones
+		.iter()
+		.filter(|parent| parent.name != zone && is_in_zone(&parent.name, zone))
+	{
+		let records: Vec<(i64, String, String)> = sqlx::query_as(indoc! {"
+			SELECT id, subdomain, type FROM records WHERE zoneid = ?1;
+		"})
+		.bind(parent.id)
+		.fetch_all(&mut **tx)
+		.await
+		.wrap_err("Cannot SELECT rows from records table")?;
+
+		let mut moved = 0;
+		for (id, subdomain, type_) in records {
+			let name = fqdn(&parent.name, &subdomain);
+			if !is_in_zone(zone, &normalize_name(&name))
+				// NS records at the apex of the subzone delegate it, so they stay in the parent
+				|| (normalize_name(&name) == zone && type_ == "NS")
+			{
+				continue;
+			}
+			let (_, subdomain) = split_at_zone(zone, &name)?;
+			// A record that is in several parents at once is only kept once
+			sqlx::query(indoc! {"
+				UPDATE OR REPLACE records SET zoneid = ?2, subdomain = ?3 WHERE id = ?1;
+			"})
+			.bind(id)
+			.bind(zoneid)
+			.bind(subdomain)
+			.execute(&mut **tx)
+			.await
+			.wrap_err("Cannot UPDATE row in records table")?;
+			moved += 1;
+		}
+		if moved > 0 {
+			println!(
+				"Moved {moved} records from the zone {} into the new zone {zone}",
+				parent.name
+			);
+		}
+	}
+
+	Ok(())
+}
+
 pub async fn optionally_create_transaction<'a>(
 	pool: &'a Pool<Sqlite>,
 	optional_tx: Option<Transaction<'a, Sqlite>>,
@@ -98,67 +415,153 @@ pub async fn optionally_rollback_transaction(
 	Ok(None)
 }
 
+pub async fn begin_savepoint<'c>(
+	tx: &'c mut Transaction<'_, Sqlite>,
+) -> Result<Transaction<'c, Sqlite>> {
+	// Beginning a transaction inside of a transaction creates a savepoint
+	tx.begin().await.wrap_err("Cannot create savepoint")
+}
+
+/// Keep the changes of a single command if it succeeded or undo just that command if it failed.
+/// The outer result is an error if the database itself misbehaved, the inner result is the result of the command.
+pub async fn end_savepoint(
+	savepoint: Transaction<'_, Sqlite>,
+	result: Result<()>,
+) -> Result<Result<()>> {
+	match result {
+		Ok(()) => {
+			savepoint
+				.commit()
+				.await
+				.wrap_err("Cannot release savepoint")?;
+		}
+		Err(_) => {
+			savepoint
+				.rollback()
+				.await
+				.wrap_err("Cannot roll back to savepoint")?;
+		}
+	}
+	Ok(result)
+}
+
 pub async fn add(
-	r: crate::parse::Add<'_>,
+	r: crate::parse::Add,
 	tx: &mut Transaction<'_, Sqlite>,
-	tld_ext: &TldExtractor,
+	scope: &Scope<'_>,
 ) -> Result<()> {
-	let (zone, subdomain) = tld_to_zone_and_subdomain(tld_ext, r.name)?;
+	let (zone, subdomain) = zone_and_subdomain(tx, scope, &r.name).await?;
+	let ttl = r
+		.ttl
+		.ok_or_else(|| eyre!("No TTL was given and no default TTL was set with the ttl command"))?;
+	let class = r.class.as_deref().unwrap_or("IN");
+	let type_ = r.data.record_type().to_string();
 
-	let zone_row = sqlx::query(indoc! {"
-		INSERT OR IGNORE INTO zones (name) VALUES (?1);
-		SELECT id FROM zones WHERE name = ?1;
+	let zoneid = insert_zone(tx, &zone).await?;
+
+	resolve_cname_conflicts(
+		tx,
+		zoneid,
+		&fqdn(&zone, &subdomain),
+		&subdomain,
+		class,
+		&r.data,
+	)
+	.await?;
+
+	// Adding a record that already exists does nothing except for updating the TTL below
+	sqlx::query(indoc! {"
+		INSERT OR IGNORE INTO records (zoneid, subdomain, ttl, class, type, data)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6);
 	"})
-	.bind(&zone)
-	.fetch_one(&mut **tx)
+	.bind(zoneid)
+	.bind(&subdomain)
+	.bind(ttl)
+	.bind(class)
+	.bind(&type_)
+	.bind(r.data.to_string())
+	.execute(&mut **tx)
 	.await
-	.wrap_err("Cannot SELECT row from zones table")?;
+	.wrap_err("Cannot INSERT row into records table")?;
 
-	let zoneid: i64 = zone_row
-		.try_get("id")
-		.wrap_err("Cannot get id from zones table")?;
-
-	let record_row = sqlx::query(indoc! {"
-		SELECT id FROM records WHERE zoneid = ?1 AND subdomain = ?2 AND class = ?3 AND type = ?4;
+	// All records of an RRset must have the same TTL (RFC 2181 section 5.2),
+	// so the TTL of the new record applies to the whole RRset, just like in BIND
+	sqlx::query(indoc! {"
+		UPDATE records SET ttl = ?5
+		WHERE zoneid = ?1 AND subdomain = ?2 AND class = ?3 AND type = ?4;
 	"})
 	.bind(zoneid)
 	.bind(&subdomain)
-	.bind(r.class)
-	.bind(r.type_)
-	.fetch_optional(&mut **tx)
+	.bind(class)
+	.bind(&type_)
+	.bind(ttl)
+	.execute(&mut **tx)
 	.await
-	.wrap_err("Cannot SELECT row from records table")?;
+	.wrap_err("Cannot UPDATE rows in records table")?;
 
-	match record_row {
-		Some(row) => {
-			let recordid: i64 = row
-				.try_get("id")
-				.wrap_err("Cannot get id from records table")?;
-			sqlx::query(indoc! {"
-				UPDATE records SET ttl = ?2, data = ?3
-				WHERE id = ?1;
-			"})
-			.bind(recordid)
-			.bind(r.ttl)
-			.bind(r.data)
-			.execute(&mut **tx)
-			.await
-			.wrap_err("Cannot UPDATE row in records table")?;
+	Ok(())
+}
+
+/// A CNAME cannot exist together with other data of the same name and class, so there is only one per name
+/// and none at the apex of a zone (RFC 1034 section 3.6.2, RFC 2181 section 10.1).
+/// Adding a CNAME replaces the existing one, just like in BIND (RFC 2136 section 3.4.2.2),
+/// every other conflict is rejected because otherwise the zone would fail to load.
+async fn resolve_cname_conflicts(
+	tx: &mut Transaction<'_, Sqlite>,
+	zoneid: i64,
+	name: &str,
+	subdomain: &str,
+	class: &str,
+	data: &RData,
+) -> Result<()> {
+	let is_cname = data.record_type() == RecordType::Cname;
+	if is_cname && subdomain == "@" {
+		bail!("Cannot add a CNAME record to {name} because it is the apex of the zone");
+	}
+
+	let existing_types: Vec<String> = sqlx::query_scalar(indoc! {"
+		SELECT DISTINCT type FROM records
+		WHERE zoneid = ?1 AND subdomain = ?2 AND class = ?3
+		ORDER BY type;
+	"})
+	.bind(zoneid)
+	.bind(subdomain)
+	.bind(class)
+	.fetch_all(&mut **tx)
+	.await
+	.wrap_err("Cannot SELECT rows from records table")?;
+
+	let cname = RecordType::Cname.to_string();
+	let has_cname = existing_types.contains(&cname);
+	if !is_cname && has_cname {
+		bail!(
+			"Cannot add a record of type {} to {name} because it already has a CNAME record",
+			data.record_type()
+		);
+	}
+	if is_cname {
+		let other_types: Vec<&str> = existing_types
+			.iter()
+			.filter(|type_| **type_ != cname)
+			.map(String::as_str)
+			.collect();
+		if !other_types.is_empty() {
+			bail!(
+				"Cannot add a CNAME record to {name} because it already has {} records",
+				other_types.join(", ")
+			);
 		}
-		None => {
+		if has_cname {
 			sqlx::query(indoc! {"
-				INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
-				VALUES (?1, ?2, ?3, ?4, ?5, ?6);
+				DELETE FROM records
+				WHERE zoneid = ?1 AND subdomain = ?2 AND class = ?3 AND type = 'CNAME';
 			"})
 			.bind(zoneid)
-			.bind(&subdomain)
-			.bind(r.ttl)
-			.bind(r.class)
-			.bind(r.type_)
-			.bind(r.data)
+			.bind(subdomain)
+			.bind(class)
 			.execute(&mut **tx)
 			.await
-			.wrap_err("Cannot INSERT row into records table")?;
+			.wrap_err("Cannot DELETE from records table")?;
 		}
 	}
 
@@ -181,15 +584,18 @@ pub async fn drop(tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
 }
 
 pub async fn delete(
-	r: crate::parse::Delete<'_>,
+	r: crate::parse::Delete,
 	tx: &mut Transaction<'_, Sqlite>,
-	tld_ext: &TldExtractor,
+	scope: &Scope<'_>,
 ) -> Result<()> {
-	let (zone, subdomain) = tld_to_zone_and_subdomain(tld_ext, r.name)?;
+	let (zone, subdomain) = zone_and_subdomain(tx, scope, &r.name).await?;
 
+	// A NULL type or data matches every record
 	sqlx::query(indoc! {"
 		DELETE FROM records
-		WHERE subdomain = ?2 AND class = ?3 AND type = ?4
+		WHERE subdomain = ?2 AND class = ?3
+		AND (?4 IS NULL OR type = ?4)
+		AND (?5 IS NULL OR data = ?5)
 		AND zoneid = (
 			SELECT id
 			FROM zones
@@ -198,8 +604,9 @@ pub async fn delete(
 	"})
 	.bind(zone)
 	.bind(subdomain)
-	.bind(r.class)
-	.bind(r.type_)
+	.bind(r.class.as_deref().unwrap_or("IN"))
+	.bind(r.type_.map(|type_| type_.to_string()))
+	.bind(r.data.as_ref().map(ToString::to_string))
 	.execute(&mut **tx)
 	.await
 	.wrap_err("Cannot DELETE from records table")?;
@@ -207,6 +614,227 @@ pub async fn delete(
 	Ok(())
 }
 
+/// Absolute name with the trailing dot
+fn fqdn(zone: &str, subdomain: &str) -> String {
+	if subdomain == "@" {
+		format!("{zone}.")
+	} else {
+		format!("{subdomain}.{zone}.")
+	}
+}
+
+/// Response code of a rejected update (RFC 2136 section 2.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rcode {
+	NxDomain,
+	YxDomain,
+	NxRrset,
+	YxRrset,
+}
+
+impl fmt::Display for Rcode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::NxDomain => "NXDOMAIN",
+			Self::YxDomain => "YXDOMAIN",
+			Self::NxRrset => "NXRRSET",
+			Self::YxRrset => "YXRRSET",
+		})
+	}
+}
+
+/// Prerequisite whose name was already assigned to a zone, it is checked when the update is sent
+pub struct PendingPrereq {
+	condition: Condition,
+	zone: String,
+	subdomain: String,
+	class: Option<String>,
+	type_: Option<RecordType>,
+	data: Option<RData>,
+}
+
+impl fmt::Display for PendingPrereq {
+	/// Display the prerequisite as a record of the prerequisite section (RFC 2136 section 2.4), like `nsupdate` does
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let class = match self.condition {
+			Condition::Nxdomain | Condition::Nxrrset => "NONE",
+			Condition::Yxdomain => "ANY",
+			Condition::Yxrrset if self.data.is_none() => "ANY",
+			Condition::Yxrrset => self.class.as_deref().unwrap_or("IN"),
+		};
+		write!(
+			f,
+			"{}\t0\t{class}\t{}",
+			fqdn(&self.zone, &self.subdomain),
+			self.type_
+				.map_or_else(|| "ANY".to_string(), |type_| type_.to_string())
+		)?;
+		if let Some(data) = &self.data {
+			write!(f, "\t{data}")?;
+		}
+		Ok(())
+	}
+}
+
+pub async fn resolve_prereq(
+	prereq: crate::parse::Prereq,
+	tx: &mut Transaction<'_, Sqlite>,
+	scope: &Scope<'_>,
+) -> Result<PendingPrereq> {
+	let (zone, subdomain) = zone_and_subdomain(tx, scope, &prereq.name).await?;
+	Ok(PendingPrereq {
+		condition: prereq.condition,
+		zone,
+		subdomain,
+		// Prerequisites about names apply to all classes
+		class: match prereq.condition {
+			Condition::Nxdomain | Condition::Yxdomain => None,
+			Condition::Nxrrset | Condition::Yxrrset => {
+				Some(prereq.class.unwrap_or_else(|| "IN".to_string()))
+			}
+		},
+		type_: prereq.type_,
+		data: prereq.data,
+	})
+}
+
+/// Check the prerequisites against the zones as they were before the pending update (RFC 2136 section 3.2).
+/// The connection must not be the one of the pending transaction, so it does not see the pending update.
+pub async fn check_prereqs(
+	connection: &mut SqliteConnection,
+	prereqs: &[PendingPrereq],
+) -> Result<Option<Rcode>> {
+	// The value-dependent prerequisites of an RRset together describe the whole RRset
+	let mut expected_rrsets: BTreeMap<(&str, &str, &str, String), BTreeSet<String>> =
+		BTreeMap::new();
+	for prereq in prereqs {
+		let data = existing_data(
+			connection,
+			&prereq.zone,
+			&prereq.subdomain,
+			prereq.class.as_deref(),
+			prereq.type_.map(|type_| type_.to_string()).as_deref(),
+		)
+		.await?;
+		let rcode = match (prereq.condition, &prereq.data) {
+			(Condition::Nxdomain, _) if !data.is_empty() => Some(Rcode::YxDomain),
+			(Condition::Yxdomain, _) if data.is_empty() => Some(Rcode::NxDomain),
+			(Condition::Nxrrset, _) if !data.is_empty() => Some(Rcode::YxRrset),
+			(Condition::Yxrrset, None) if data.is_empty() => Some(Rcode::NxRrset),
+			(Condition::Yxrrset, Some(expected)) => {
+				let key = (
+					prereq.zone.as_str(),
+					prereq.subdomain.as_str(),
+					prereq.class.as_deref().unwrap_or_default(),
+					prereq
+						.type_
+						.map(|type_| type_.to_string())
+						.unwrap_or_default(),
+				);
+				expected_rrsets
+					.entry(key)
+					.or_default()
+					.insert(expected.to_string());
+				None
+			}
+			_ => None,
+		};
+		if rcode.is_some() {
+			return Ok(rcode);
+		}
+	}
+	for ((zone, subdomain, class, type_), expected) in expected_rrsets {
+		let data = existing_data(connection, zone, subdomain, Some(class), Some(&type_)).await?;
+		if !data.into_iter().eq(expected) {
+			return Ok(Some(Rcode::NxRrset));
+		}
+	}
+	Ok(None)
+}
+
+/// Data of the matching records, sorted and without duplicates. A `None` class or type matches everything.
+async fn existing_data(
+	connection: &mut SqliteConnection,
+	zone: &str,
+	subdomain: &str,
+	class: Option<&str>,
+	type_: Option<&str>,
+) -> Result<Vec<String>> {
+	sqlx::query_scalar(indoc! {"
+		SELECT DISTINCT data
+		FROM records
+		INNER JOIN zones ON zones.id = records.zoneid
+		WHERE zones.name = ?1 AND subdomain = ?2 AND (?3 IS NULL OR class = ?3) AND (?4 IS NULL OR type = ?4)
+		ORDER BY data;
+	"})
+	.bind(zone)
+	.bind(subdomain)
+	.bind(class)
+	.bind(type_)
+	.fetch_all(connection)
+	.await
+	.wrap_err("Cannot SELECT rows from records table")
+}
+
+/// Display an internationalized name with its U-labels, the database only contains A-labels
+fn to_unicode_name(name: &str) -> String {
+	idna::domain_to_unicode(name).0
+}
+
+/// List the records of the zone, or of all zones, including the pending update, with the names in U-label form
+pub async fn list(connection: &mut SqliteConnection, zone: Option<&str>) -> Result<String> {
+	let rows = sqlx::query_as::<_, ZoneResourceRecord>(indoc! {"
+		SELECT
+			zones.name AS zone,
+			subdomain,
+			ttl,
+			class,
+			type AS type_,
+			data
+		FROM
+			records
+		INNER JOIN zones ON zones.id = records.zoneid
+		WHERE ?1 IS NULL OR zones.name = ?1
+		ORDER BY zones.name, subdomain, class, type, ttl, data;
+	"})
+	.bind(zone.map(normalize_name))
+	.fetch_all(connection)
+	.await
+	.wrap_err("Cannot list the records")?;
+
+	let mut listing = String::new();
+	for record in rows {
+		let name = fqdn(&record.zone, &record.subdomain);
+		writeln!(
+			listing,
+			"{: <30} {: >6} {: <3} {: <5} {}",
+			to_unicode_name(&name),
+			record.ttl,
+			record.class,
+			record.type_,
+			parse::stored_rdata(&record.type_, &record.data).map_or_else(
+				|| record.data.clone(),
+				|rdata| rdata.map_names(to_unicode_name).to_string()
+			)
+		)
+		.expect("writing to a String should never fail");
+	}
+	Ok(listing)
+}
+
+/// Records of types that zonegen does not know, e.g. DHCID or DS records that were added by older versions,
+/// are published as they were stored, as long as they cannot break the zone file
+fn is_verbatim_record(record: &ResourceRecord) -> bool {
+	record.type_.parse::<RecordType>().is_err()
+		&& !record.type_.is_empty()
+		&& record.type_.bytes().all(|b| b.is_ascii_alphanumeric())
+		&& !record.data.is_empty()
+		&& record
+			.data
+			.bytes()
+			.all(|b| b.is_ascii_graphic() || b == b' ')
+}
+
 pub async fn save_zones(pool: &Pool<Sqlite>, dir: PathBuf) -> Result<()> {
 	let mut zone_rows = sqlx::query_as::<_, Zone>(indoc! {"
 		SELECT id, name FROM zones
@@ -240,14 +868,987 @@ pub async fn save_zones(pool: &Pool<Sqlite>, dir: PathBuf) -> Result<()> {
 
 		while let Some(maybe_row) = rows.next().await {
 			let record = maybe_row.wrap_err("Cannot get row from records table")?;
-			let zone_data_line = format!(
-				"{: <20} {: >6} {: <3} {: <5} {}\n",
-				record.subdomain, record.ttl, record.class, record.type_, record.data
-			);
-			zone_data.push_str(&zone_data_line);
+			let line = if let Some(rdata) = parse::stored_rdata(&record.type_, &record.data) {
+				zone_files::record_line(&record.subdomain, record.ttl, &record.class, &rdata)
+			} else if is_verbatim_record(&record) {
+				zone_files::verbatim_record_line(
+					&record.subdomain,
+					record.ttl,
+					&record.class,
+					&record.type_,
+					&record.data,
+				)
+			} else {
+				// Leaving the record out would silently unpublish it and writing it would make the nameserver reject the whole zone
+				bail!(
+					"The record `{} {} {} {}` of the zone {} is invalid, delete it with `update delete` and add it again",
+					record.subdomain,
+					record.class,
+					record.type_,
+					record.data,
+					zone.name
+				);
+			};
+			zone_data.push_str(&line);
 		}
 		zone_files::save(&zone.name, &zone_data, &dir)?;
 	}
 
 	Ok(())
 }
+
+#[cfg(test)]
+pub mod test {
+	use super::{
+		Rcode, Scope, add, begin_savepoint, check_prereqs, declare_zones, delete, end_savepoint,
+		init, resolve_prereq, save_zones, setup, split_at_zone,
+	};
+	use crate::parse::{self, Command, Update};
+	use crate::psl::PublicSuffixList;
+	use crate::record::{RData, ServiceBinding};
+	use color_eyre::eyre::{Result, eyre};
+	use indoc::indoc;
+	use sqlx::{Pool, Sqlite, Transaction, sqlite::SqlitePoolOptions};
+	use std::{fs, path::PathBuf, process};
+
+	/// Database file that is removed at the end of the test, for tests that need more than one connection
+	pub struct TempDatabase {
+		pub pool: Pool<Sqlite>,
+		dir: PathBuf,
+	}
+
+	impl TempDatabase {
+		pub async fn new(name: &str) -> Self {
+			let dir = std::env::temp_dir().join(format!("zonegen-{name}-{}", process::id()));
+			// Left behind by an earlier test run that was aborted
+			let _ = fs::remove_dir_all(&dir);
+			fs::create_dir_all(&dir).expect("Cannot create temporary directory");
+			let pool = init(&dir).await.expect("Cannot open database");
+			Self { pool, dir }
+		}
+	}
+
+	impl Drop for TempDatabase {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.dir);
+		}
+	}
+
+	/// Empty database that only exists for the duration of the test
+	async fn empty_memory_pool() -> Pool<Sqlite> {
+		// Each connection would get a database of its own, so there must only be one which is never closed
+		SqlitePoolOptions::new()
+			.max_connections(1)
+			.idle_timeout(None)
+			.max_lifetime(None)
+			.connect("sqlite::memory:")
+			.await
+			.expect("Cannot open in-memory database")
+	}
+
+	async fn memory_pool() -> Pool<Sqlite> {
+		let pool = empty_memory_pool().await;
+		setup(&pool).await.expect("Cannot set up database");
+		pool
+	}
+
+	/// Database with the schema of the given migration and the given SQL statements run on it
+	async fn migrated_memory_pool(version: i64, statements: &'static str) -> Pool<Sqlite> {
+		let pool = empty_memory_pool().await;
+		sqlx::migrate!("./migrations")
+			.run_to(version, &pool)
+			.await
+			.expect("Cannot run database migrations");
+		sqlx::raw_sql(statements)
+			.execute(&pool)
+			.await
+			.expect("Cannot run SQL statements");
+		pool
+	}
+
+	async fn declare(pool: &Pool<Sqlite>, zones: &[&str]) {
+		let zones: Vec<String> = zones.iter().map(ToString::to_string).collect();
+		declare_zones(pool, &zones)
+			.await
+			.expect("Cannot declare zones");
+	}
+
+	/// Apply an `update add` or `update delete` command to the pending transaction
+	async fn update(tx: &mut Transaction<'_, Sqlite>, line: &str) -> Result<()> {
+		let scope = Scope {
+			psl: None,
+			zone: None,
+			origin: None,
+		};
+		update_in(tx, &scope, line).await
+	}
+
+	/// Like `update`, but with the zone and the public suffix list of the scope
+	async fn update_in(
+		tx: &mut Transaction<'_, Sqlite>,
+		scope: &Scope<'_>,
+		line: &str,
+	) -> Result<()> {
+		match parse::parse(line) {
+			Ok(Command::Update(Update::Add(r))) => add(r, tx, scope).await,
+			Ok(Command::Update(Update::Delete(r))) => delete(r, tx, scope).await,
+			_ => panic!("`{line}` is not an update"),
+		}
+	}
+
+	/// Apply the updates in a transaction of their own
+	async fn commit(pool: &Pool<Sqlite>, lines: &[&str]) {
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+		for line in lines {
+			update(&mut tx, line)
+				.await
+				.unwrap_or_else(|e| panic!("`{line}` failed: {e:#}"));
+		}
+		tx.commit().await.expect("Cannot commit transaction");
+	}
+
+	/// Message of the error that applying the update to the pending transaction fails with
+	async fn error(tx: &mut Transaction<'_, Sqlite>, line: &str) -> String {
+		let e = update(tx, line)
+			.await
+			.expect_err(&format!("`{line}` did not fail"));
+		format!("{e:#}")
+	}
+
+	/// Check the prerequisites against the committed state, while they are resolved in the pending transaction
+	async fn check(
+		pool: &Pool<Sqlite>,
+		tx: &mut Transaction<'_, Sqlite>,
+		lines: &[&str],
+	) -> Option<Rcode> {
+		let scope = Scope {
+			psl: None,
+			zone: None,
+			origin: None,
+		};
+		let mut prereqs = Vec::new();
+		for line in lines {
+			let Ok(Command::Prereq(prereq)) = parse::parse(line) else {
+				panic!("`{line}` is not a prerequisite");
+			};
+			prereqs.push(
+				resolve_prereq(prereq, tx, &scope)
+					.await
+					.expect("Cannot resolve prerequisite"),
+			);
+		}
+		let mut connection = pool.acquire().await.expect("Cannot acquire connection");
+		check_prereqs(&mut connection, &prereqs)
+			.await
+			.expect("Cannot check prerequisites")
+	}
+
+	/// Every record as `zone subdomain ttl class type data`, sorted
+	async fn records<'c, E: sqlx::SqliteExecutor<'c>>(executor: E) -> Vec<String> {
+		let rows: Vec<(String, String, i64, String, String, String)> = sqlx::query_as(indoc! {"
+				SELECT zones.name, subdomain, ttl, class, type, data
+				FROM records JOIN zones ON records.zoneid = zones.id
+				ORDER BY zones.name, subdomain, type, data;
+			"})
+		.fetch_all(executor)
+		.await
+		.expect("Cannot SELECT records");
+		rows.into_iter()
+			.map(|(zone, subdomain, ttl, class, type_, data)| {
+				format!("{zone} {subdomain} {ttl} {class} {type_} {data}")
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn rrset_migration_test() {
+		let pool = migrated_memory_pool(
+			0,
+			indoc! {"
+				INSERT INTO zones (name) VALUES ('example.org');
+				INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
+				VALUES (1, 'www', 300, 'IN', 'A', '192.0.2.1');
+			"},
+		)
+		.await;
+		setup(&pool).await.expect("Cannot set up database");
+		commit(&pool, &["update add www.example.org. 300 IN A 192.0.2.2"]).await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example.org www 300 IN A 192.0.2.1",
+				"example.org www 300 IN A 192.0.2.2",
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn rrset_test() {
+		let pool = memory_pool().await;
+		commit(
+			&pool,
+			&[
+				"update add www.example. 300 IN A 192.0.2.1",
+				"update add www.example. 300 IN A 192.0.2.2",
+				"update add www.example. 300 IN TXT \"a\"",
+				"update add www.example. 300 IN TXT \"b\"",
+				// Adding a record that already exists does not duplicate it
+				"update add www.example. 300 IN A 192.0.2.1",
+			],
+		)
+		.await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example www 300 IN A 192.0.2.1",
+				"example www 300 IN A 192.0.2.2",
+				"example www 300 IN TXT \"a\"",
+				"example www 300 IN TXT \"b\"",
+			]
+		);
+
+		// The TTL of the last added record applies to the whole RRset, but not to other RRsets
+		commit(&pool, &["update add www.example. 60 IN A 192.0.2.3"]).await;
+		commit(&pool, &["update add www.example. 600 IN TXT \"a\""]).await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example www 60 IN A 192.0.2.1",
+				"example www 60 IN A 192.0.2.2",
+				"example www 60 IN A 192.0.2.3",
+				"example www 600 IN TXT \"a\"",
+				"example www 600 IN TXT \"b\"",
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn delete_test() {
+		let pool = memory_pool().await;
+		commit(
+			&pool,
+			&[
+				"update add www.example. 300 IN A 192.0.2.1",
+				"update add www.example. 300 IN A 192.0.2.2",
+				"update add www.example. 300 IN TXT \"text\"",
+				"update add www.example. 300 CH TXT \"chaos\"",
+				"update add sub.www.example. 300 IN A 192.0.2.3",
+				"update add mail.example. 300 IN A 192.0.2.4",
+				"update add mail.example. 300 IN MX 10 mail.example.",
+			],
+		)
+		.await;
+
+		// A single record
+		commit(&pool, &["update delete www.example. A 192.0.2.1"]).await;
+		// An RRset, the TTL is ignored
+		commit(&pool, &["update delete www.example. 0 IN TXT"]).await;
+		// Data that does not exist is not an error
+		commit(&pool, &["update delete www.example. A 192.0.2.9"]).await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example mail 300 IN A 192.0.2.4",
+				"example mail 300 IN MX 10 mail.example.",
+				"example sub.www 300 IN A 192.0.2.3",
+				"example www 300 IN A 192.0.2.2",
+				"example www 300 CH TXT \"chaos\"",
+			]
+		);
+
+		// Every RRset of the name in the class, but not the names below it
+		commit(
+			&pool,
+			&[
+				"update delete www.example.",
+				"update delete mail.example. IN",
+			],
+		)
+		.await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example sub.www 300 IN A 192.0.2.3",
+				"example www 300 CH TXT \"chaos\"",
+			]
+		);
+		commit(&pool, &["update delete www.example. CH"]).await;
+		assert_eq!(records(&pool).await, ["example sub.www 300 IN A 192.0.2.3"]);
+	}
+
+	#[tokio::test]
+	async fn savepoint_test() {
+		let pool = memory_pool().await;
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+		update(&mut tx, "update add www.example. 300 IN A 192.0.2.1")
+			.await
+			.expect("Cannot add record");
+
+		// A failed command is undone completely, including the zone it created
+		let mut savepoint = begin_savepoint(&mut tx)
+			.await
+			.expect("Cannot create savepoint");
+		update(&mut savepoint, "update add www.test. 300 IN A 192.0.2.2")
+			.await
+			.expect("Cannot add record");
+		let result = end_savepoint(savepoint, Err(eyre!("failed")))
+			.await
+			.expect("Cannot roll back to savepoint");
+		assert!(result.is_err());
+
+		let mut savepoint = begin_savepoint(&mut tx)
+			.await
+			.expect("Cannot create savepoint");
+		update(&mut savepoint, "update delete www.example.")
+			.await
+			.expect("Cannot delete name");
+		let result = end_savepoint(savepoint, Err(eyre!("failed")))
+			.await
+			.expect("Cannot roll back to savepoint");
+		assert!(result.is_err());
+
+		// A successful command is kept as part of the pending transaction
+		let mut savepoint = begin_savepoint(&mut tx)
+			.await
+			.expect("Cannot create savepoint");
+		let result = update(
+			&mut savepoint,
+			"update add mail.example. 300 IN A 192.0.2.3",
+		)
+		.await;
+		assert!(
+			end_savepoint(savepoint, result)
+				.await
+				.expect("Cannot release savepoint")
+				.is_ok()
+		);
+
+		tx.commit().await.expect("Cannot commit transaction");
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example mail 300 IN A 192.0.2.3",
+				"example www 300 IN A 192.0.2.1",
+			]
+		);
+		let zones: Vec<String> = sqlx::query_scalar("SELECT name FROM zones;")
+			.fetch_all(&pool)
+			.await
+			.expect("Cannot SELECT zones");
+		assert_eq!(zones, ["example"]);
+	}
+
+	#[tokio::test]
+	async fn legacy_record_test() {
+		let database = TempDatabase::new("legacy-records").await;
+		let pool = &database.pool;
+		// Records as older versions stored them, they accepted any type with data without spaces
+		sqlx::raw_sql(indoc! {"
+			INSERT INTO zones (name) VALUES ('example.org');
+			INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
+			VALUES (1, 'host', 300, 'IN', 'DHCID', 'AAIBY2/AuCccgoJbsaxcQc9TUapptP69lOjxfNuVAA2kjEA='),
+				(1, 'ds', 300, 'IN', 'DS', '60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118'),
+				(1, 'www', 300, 'IN', 'A', '192.0.2.1');
+		"})
+		.execute(pool)
+		.await
+		.expect("Cannot insert records");
+		save_zones(pool, database.dir.clone())
+			.await
+			.expect("Cannot save zones");
+		let zone_file = fs::read_to_string(database.dir.join("example.org.zone"))
+			.expect("Cannot read zone file");
+		assert!(zone_file.contains(
+			"ds                      300 IN  DS    60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118\n"
+		));
+		assert!(zone_file.contains(
+			"host                    300 IN  DHCID AAIBY2/AuCccgoJbsaxcQc9TUapptP69lOjxfNuVAA2kjEA=\n"
+		));
+		assert!(zone_file.contains("www                     300 IN  A     192.0.2.1\n"));
+
+		// Invalid data of a known type is not left out silently
+		sqlx::raw_sql(indoc! {"
+			INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
+			VALUES (1, 'broken', 300, 'IN', 'A', 'banana');
+		"})
+		.execute(pool)
+		.await
+		.expect("Cannot insert record");
+		assert!(save_zones(pool, database.dir.clone()).await.is_err());
+		assert_eq!(
+			fs::read_to_string(database.dir.join("example.org.zone")).ok(),
+			Some(zone_file)
+		);
+	}
+
+	#[tokio::test]
+	async fn quote_txt_migration_test() {
+		let pool = migrated_memory_pool(
+			1,
+			indoc! {"
+				INSERT INTO zones (name) VALUES ('example');
+				INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
+				VALUES (1, 'a', 300, 'IN', 'TXT', 'v=spf1 -all'),
+					(1, 'a', 300, 'IN', 'SPF', 'v=spf1 -all'),
+					(1, 'b', 300, 'IN', 'TXT', '\"already quoted\"'),
+					(1, 'c', 300, 'IN', 'TXT', 'token'),
+					(1, 'c', 300, 'IN', 'TXT', '\"token\"'),
+					(1, 'd', 300, 'IN', 'CNAME', 'a.example.'),
+					(1, 'e', 300, 'IN', 'TXT', 'say:\"hi\"');
+			"},
+		)
+		.await;
+		setup(&pool).await.expect("Cannot set up database");
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example a 300 IN SPF \"v=spf1 -all\"",
+				"example a 300 IN TXT \"v=spf1 -all\"",
+				"example b 300 IN TXT \"already quoted\"",
+				// The duplicate is removed
+				"example c 300 IN TXT \"token\"",
+				"example d 300 IN CNAME a.example.",
+				r#"example e 300 IN TXT "say:\"hi\"""#,
+			]
+		);
+		// Quotes inside of the data are escaped, so that it can still be read
+		let data: String = sqlx::query_scalar("SELECT data FROM records WHERE subdomain = 'e';")
+			.fetch_one(&pool)
+			.await
+			.expect("Cannot SELECT record");
+		assert_eq!(
+			parse::stored_rdata("TXT", &data),
+			Some(RData::Txt(vec![br#"say:"hi""#.to_vec()]))
+		);
+	}
+
+	#[tokio::test]
+	async fn canonical_address_test() {
+		let pool = migrated_memory_pool(
+			3,
+			indoc! {"
+				INSERT INTO zones (name) VALUES ('example');
+				INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
+				VALUES (1, 'a', 300, 'IN', 'AAAA', '2001:DB8:0:0::1'),
+					(1, 'b', 300, 'IN', 'AAAA', '2001:0db8::0002'),
+					(1, 'b', 300, 'IN', 'AAAA', '2001:db8::2'),
+					(1, 'c', 300, 'IN', 'A', '192.0.2.1'),
+					(1, 'c', 300, 'IN', 'A', '192.000.002.010'),
+					(1, 'd', 300, 'IN', 'AAAA', 'invalid'),
+					(1, 'd', 300, 'IN', 'A', '192.0.2.+1'),
+					(1, 'e', 300, 'IN', 'TXT', '\"2001:DB8::1\"');
+			"},
+		)
+		.await;
+		setup(&pool).await.expect("Cannot set up database");
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example a 300 IN AAAA 2001:db8::1",
+				"example b 300 IN AAAA 2001:db8::2",
+				"example c 300 IN A 192.0.2.1",
+				"example c 300 IN A 192.0.2.10",
+				"example d 300 IN A 192.0.2.+1",
+				"example d 300 IN AAAA invalid",
+				"example e 300 IN TXT \"2001:DB8::1\"",
+			]
+		);
+
+		// Addresses are compared by value
+		commit(
+			&pool,
+			&["update delete a.example. AAAA 2001:0DB8:0:0:0:0:0:1"],
+		)
+		.await;
+		commit(
+			&pool,
+			&["update add b.example. 300 IN AAAA 2001:0db8::0002"],
+		)
+		.await;
+		assert_eq!(
+			records(&pool).await[..2],
+			[
+				"example b 300 IN AAAA 2001:db8::2",
+				"example c 300 IN A 192.0.2.1",
+			]
+		);
+
+		// The addresses are only rewritten once and not on every start
+		sqlx::raw_sql("UPDATE records SET data = '2001:DB8::2' WHERE subdomain = 'b';")
+			.execute(&pool)
+			.await
+			.expect("Cannot UPDATE record");
+		setup(&pool).await.expect("Cannot set up database");
+		assert_eq!(records(&pool).await[0], "example b 300 IN AAAA 2001:DB8::2");
+	}
+
+	#[tokio::test]
+	async fn ttl_limit_migration_test() {
+		let pool = migrated_memory_pool(
+			2,
+			indoc! {"
+				INSERT INTO zones (name) VALUES ('example.org');
+				INSERT INTO records (zoneid, subdomain, ttl, class, type, data)
+				VALUES (1, 'max', 4294967295, 'IN', 'A', '192.0.2.1'),
+					(1, 'limit', 2147483647, 'IN', 'A', '192.0.2.2'),
+					(1, 'low', 300, 'IN', 'A', '192.0.2.3');
+			"},
+		)
+		.await;
+		setup(&pool).await.expect("Cannot set up database");
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example.org limit 2147483647 IN A 192.0.2.2",
+				"example.org low 300 IN A 192.0.2.3",
+				"example.org max 2147483647 IN A 192.0.2.1",
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn subzone_test() {
+		let pool = memory_pool().await;
+		declare(&pool, &["example.org"]).await;
+		commit(
+			&pool,
+			&[
+				"update add host.dyn.example.org. 300 IN A 192.0.2.1",
+				"update add dyn.example.org. 300 IN NS ns.example.org.",
+				"update add dyn.example.org. 300 IN TXT \"apex\"",
+				"update add www.example.org. 300 IN A 192.0.2.2",
+			],
+		)
+		.await;
+
+		declare(&pool, &["dyn.example.org"]).await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"dyn.example.org @ 300 IN TXT \"apex\"",
+				"dyn.example.org host 300 IN A 192.0.2.1",
+				"example.org dyn 300 IN NS ns.example.org.",
+				"example.org www 300 IN A 192.0.2.2",
+			]
+		);
+
+		// The moved records can be changed again
+		commit(&pool, &["update delete host.dyn.example.org."]).await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"dyn.example.org @ 300 IN TXT \"apex\"",
+				"example.org dyn 300 IN NS ns.example.org.",
+				"example.org www 300 IN A 192.0.2.2",
+			]
+		);
+
+		// Declaring a zone that is already known does not move anything
+		declare(&pool, &["example.org"]).await;
+		assert_eq!(records(&pool).await.len(), 3);
+	}
+
+	#[tokio::test]
+	async fn cname_test() {
+		let pool = memory_pool().await;
+		declare(&pool, &["example.org"]).await;
+		commit(
+			&pool,
+			&[
+				"update add www.example.org. 300 IN A 192.0.2.1",
+				"update add alias.example.org. 300 IN CNAME www.example.org.",
+			],
+		)
+		.await;
+
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+		assert_eq!(
+			error(
+				&mut tx,
+				"update add www.example.org. 300 IN CNAME other.example.org."
+			)
+			.await,
+			"Cannot add a CNAME record to www.example.org. because it already has A records"
+		);
+		assert_eq!(
+			error(&mut tx, "update add alias.example.org. 300 IN TXT \"text\"").await,
+			"Cannot add a record of type TXT to alias.example.org. because it already has a CNAME record"
+		);
+		assert_eq!(
+			error(
+				&mut tx,
+				"update add example.org. 300 IN CNAME www.example.org."
+			)
+			.await,
+			"Cannot add a CNAME record to example.org. because it is the apex of the zone"
+		);
+
+		// The conflict can also come from an earlier update that was not sent yet
+		update(
+			&mut tx,
+			"update add new.example.org. 300 IN AAAA 2001:db8::1",
+		)
+		.await
+		.expect("Cannot add AAAA record");
+		assert_eq!(
+			error(
+				&mut tx,
+				"update add new.example.org. 300 IN CNAME www.example.org."
+			)
+			.await,
+			"Cannot add a CNAME record to new.example.org. because it already has AAAA records"
+		);
+		update(
+			&mut tx,
+			"update add other.example.org. 300 IN CNAME www.example.org.",
+		)
+		.await
+		.expect("Cannot add CNAME record");
+		assert_eq!(
+			error(
+				&mut tx,
+				"update add other.example.org. 300 IN MX 10 mail.example.org."
+			)
+			.await,
+			"Cannot add a record of type MX to other.example.org. because it already has a CNAME record"
+		);
+
+		// Another CNAME replaces the existing one
+		update(
+			&mut tx,
+			"update add alias.example.org. 600 IN CNAME new.example.org.",
+		)
+		.await
+		.expect("Cannot replace CNAME record");
+		tx.commit().await.expect("Cannot commit transaction");
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example.org alias 600 IN CNAME new.example.org.",
+				"example.org new 300 IN AAAA 2001:db8::1",
+				"example.org other 300 IN CNAME www.example.org.",
+				"example.org www 300 IN A 192.0.2.1",
+			]
+		);
+	}
+
+	/// Database in which `www.example.` has two A records and a TXT record
+	async fn prereq_database(name: &str) -> TempDatabase {
+		let database = TempDatabase::new(name).await;
+		commit(
+			&database.pool,
+			&[
+				"update add www.example. 300 IN A 192.0.2.1",
+				"update add www.example. 300 IN A 192.0.2.2",
+				"update add www.example. 300 IN TXT \"text\"",
+			],
+		)
+		.await;
+		database
+	}
+
+	#[tokio::test]
+	async fn prereq_test() {
+		let database = prereq_database("prereq").await;
+		let pool = &database.pool;
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+
+		assert_eq!(
+			check(pool, &mut tx, &["prereq nxdomain new.example."]).await,
+			None
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq nxdomain www.example."]).await,
+			Some(Rcode::YxDomain)
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxdomain www.example."]).await,
+			None
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxdomain new.example."]).await,
+			Some(Rcode::NxDomain)
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq nxrrset www.example. MX"]).await,
+			None
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq nxrrset www.example. IN TXT"]).await,
+			Some(Rcode::YxRrset)
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxrrset www.example. A"]).await,
+			None
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxrrset www.example. AAAA"]).await,
+			Some(Rcode::NxRrset)
+		);
+		// A different class is a different RRset
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxrrset www.example. CH TXT"]).await,
+			Some(Rcode::NxRrset)
+		);
+		// The first prerequisite that is not met determines the response code
+		assert_eq!(
+			check(
+				pool,
+				&mut tx,
+				&[
+					"prereq yxdomain www.example.",
+					"prereq nxrrset www.example. A"
+				]
+			)
+			.await,
+			Some(Rcode::YxRrset)
+		);
+	}
+
+	#[tokio::test]
+	async fn value_dependent_prereq_test() {
+		let database = prereq_database("value-dependent-prereq").await;
+		let pool = &database.pool;
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+		// The value-dependent prerequisites of an RRset have to match all of its records exactly
+		assert_eq!(
+			check(
+				pool,
+				&mut tx,
+				&[
+					"prereq yxrrset www.example. A 192.0.2.2",
+					"prereq yxrrset www.example. A 192.0.2.1",
+				]
+			)
+			.await,
+			None
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxrrset www.example. A 192.0.2.1"]).await,
+			Some(Rcode::NxRrset)
+		);
+		assert_eq!(
+			check(
+				pool,
+				&mut tx,
+				&[
+					"prereq yxrrset www.example. A 192.0.2.1",
+					"prereq yxrrset www.example. A 192.0.2.2",
+					"prereq yxrrset www.example. A 192.0.2.3",
+				]
+			)
+			.await,
+			Some(Rcode::NxRrset)
+		);
+		assert_eq!(
+			check(pool, &mut tx, &["prereq yxrrset www.example. TXT \"text\""]).await,
+			None
+		);
+	}
+
+	#[tokio::test]
+	async fn pending_prereq_test() {
+		let database = prereq_database("pending-prereq").await;
+		let pool = &database.pool;
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+		// The prerequisites are checked against the state before the pending update
+		update(&mut tx, "update add new.example. 300 IN A 192.0.2.3")
+			.await
+			.expect("Cannot add record");
+		update(&mut tx, "update delete www.example. TXT")
+			.await
+			.expect("Cannot delete RRset");
+		assert_eq!(
+			check(
+				pool,
+				&mut tx,
+				&[
+					"prereq nxdomain new.example.",
+					"prereq yxrrset www.example. TXT",
+				]
+			)
+			.await,
+			None
+		);
+	}
+
+	#[test]
+	fn split_at_zone_test() {
+		let split = |zone, name| split_at_zone(zone, name).ok();
+		assert_eq!(
+			split("example.org", "www.Example.org."),
+			Some(("example.org".to_string(), "www".to_string()))
+		);
+		assert_eq!(
+			split("example.org", "a.b.example.org"),
+			Some(("example.org".to_string(), "a.b".to_string()))
+		);
+		assert_eq!(
+			split("example.org", "example.org."),
+			Some(("example.org".to_string(), "@".to_string()))
+		);
+		assert_eq!(split("example.org", "www.badexample.org."), None);
+		assert_eq!(split("example.org", "example.net."), None);
+		assert_eq!(split("www.example.org", "example.org."), None);
+	}
+
+	#[tokio::test]
+	async fn explicit_zone_test() {
+		let pool = memory_pool().await;
+		declare(&pool, &["example.org", "dyn.example.org"]).await;
+		let psl = PublicSuffixList::embedded();
+		let explicit = Scope {
+			psl: Some(&psl),
+			zone: Some("example.org".to_string()),
+			origin: None,
+		};
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+
+		// The zone set with the zone command wins over a longer known zone
+		update_in(
+			&mut tx,
+			&explicit,
+			"update add a.dyn.example.org. 300 IN A 192.0.2.1",
+		)
+		.await
+		.expect("Cannot add record");
+		// Names outside of it are rejected instead of being assigned to another zone
+		let e = update_in(
+			&mut tx,
+			&explicit,
+			"update add www.example.net. 300 IN A 192.0.2.2",
+		)
+		.await
+		.expect_err("A name outside of the zone was accepted");
+		assert_eq!(
+			format!("{e:#}"),
+			"The name www.example.net is not inside of the zone example.org"
+		);
+
+		// Without a zone, the longest known zone is used or the zone is guessed again
+		let guessing = Scope {
+			zone: None,
+			..explicit
+		};
+		update_in(
+			&mut tx,
+			&guessing,
+			"update add b.dyn.example.org. 300 IN A 192.0.2.3",
+		)
+		.await
+		.expect("Cannot add record");
+		update_in(
+			&mut tx,
+			&guessing,
+			"update add www.example.net. 300 IN A 192.0.2.2",
+		)
+		.await
+		.expect("Cannot add record");
+		tx.commit().await.expect("Cannot commit transaction");
+		assert_eq!(
+			records(&pool).await,
+			[
+				"dyn.example.org b 300 IN A 192.0.2.3",
+				"example.net www 300 IN A 192.0.2.2",
+				"example.org a.dyn 300 IN A 192.0.2.1",
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn private_zone_test() {
+		let pool = memory_pool().await;
+		commit(
+			&pool,
+			&[
+				"update add printer.home.arpa. 300 IN A 192.168.1.2",
+				"update add home.arpa. 300 IN TXT \"apex\"",
+				"update add a.b.internal. 300 IN A 10.0.0.1",
+				"update add www.example. 300 IN A 192.0.2.1",
+			],
+		)
+		.await;
+		assert_eq!(
+			records(&pool).await,
+			[
+				"example www 300 IN A 192.0.2.1",
+				"home.arpa @ 300 IN TXT \"apex\"",
+				"home.arpa printer 300 IN A 192.168.1.2",
+				"internal a.b 300 IN A 10.0.0.1",
+			]
+		);
+
+		// Names below other private top-level domains are not guessed
+		let mut tx = pool.begin().await.expect("Cannot begin transaction");
+		assert!(
+			update(&mut tx, "update add nas.lan. 300 IN A 192.168.1.3")
+				.await
+				.is_err()
+		);
+		tx.rollback().await.expect("Cannot roll back transaction");
+		declare(&pool, &["lan"]).await;
+		commit(&pool, &["update add nas.lan. 300 IN A 192.168.1.3"]).await;
+		assert!(
+			records(&pool)
+				.await
+				.contains(&"lan nas 300 IN A 192.168.1.3".to_string())
+		);
+	}
+
+	#[test]
+	fn absolute_data_test() {
+		let scope = Scope {
+			psl: None,
+			zone: Some("example.org".to_string()),
+			origin: Some("sub.example.org".to_string()),
+		};
+		let absolute = |data| scope.absolute_data(data).ok();
+		assert_eq!(
+			absolute(RData::Cname("foo".to_string())),
+			Some(RData::Cname("foo.sub.example.org.".to_string()))
+		);
+		assert_eq!(
+			absolute(RData::Mx {
+				preference: 10,
+				exchange: "@".to_string()
+			}),
+			Some(RData::Mx {
+				preference: 10,
+				exchange: "sub.example.org.".to_string()
+			})
+		);
+		assert_eq!(
+			absolute(RData::Ns("ns.example.net.".to_string())),
+			Some(RData::Ns("ns.example.net.".to_string()))
+		);
+		// The target `.` of a service binding stands for the owner name
+		let binding = ServiceBinding {
+			priority: 1,
+			target: ".".to_string(),
+			params: Vec::new(),
+		};
+		assert_eq!(
+			absolute(RData::Svcb(binding.clone())),
+			Some(RData::Svcb(binding))
+		);
+
+		// Without an origin, names are relative to the zone
+		let scope = Scope {
+			origin: None,
+			..scope
+		};
+		assert_eq!(
+			scope.absolute_data(RData::Cname("foo".to_string())).ok(),
+			Some(RData::Cname("foo.example.org.".to_string()))
+		);
+		let scope = Scope {
+			zone: None,
+			..scope
+		};
+		assert!(
+			scope
+				.absolute_data(RData::Cname("foo".to_string()))
+				.is_err()
+		);
+	}
+}
@@ -1,40 +1,245 @@
 // SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::repl::repl;
-use clap::Parser;
-use color_eyre::eyre::Result;
+use crate::psl::PublicSuffixList;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use color_eyre::eyre::{Result, WrapErr};
+use std::{
+	fs::File,
+	io::{BufReader, IsTerminal},
+	path::{Path, PathBuf},
+	process::ExitCode,
+};
 
 mod db;
 mod parse;
+mod psl;
+mod record;
 mod repl;
+mod tsig;
 mod zone_files;
 
 #[derive(Parser, Debug)]
 #[command(version)]
 struct Args {
 	/// Path to the directory where the zone files will be generated and the SQLite database will be stored
-	#[arg(short, long)]
-	dir: std::path::PathBuf,
+	#[arg(short, long, env = "ZONEGEN_DIR")]
+	dir: PathBuf,
+
+	/// Zone that is managed by zonegen, can be given multiple times. Names are assigned to the longest matching zone
+	#[arg(long = "zone", value_name = "ZONE")]
+	zones: Vec<String>,
+
+	/// Do not guess the zone of names outside of all known zones from the public suffix list but reject them
+	#[arg(long, conflicts_with = "public_suffix_list")]
+	no_guess_zones: bool,
+
+	/// Read the public suffix list from this file instead of using the snapshot that is built into zonegen
+	#[arg(long, value_name = "FILE")]
+	public_suffix_list: Option<PathBuf>,
+
+	/// File with the TSIG keys that are allowed to make changes, in the format of the key statements of BIND.
+	/// If it is given, one of the keys has to be passed with -k, -y or the key command
+	#[arg(long, env = "ZONEGEN_KEYS", value_name = "FILE")]
+	keys: Option<PathBuf>,
+
+	/// Send the pending update on an empty line in the interactive mode too, like in scripts
+	#[arg(long)]
+	blank_line_sends: bool,
+
+	/// Use the meaning of the flags of nsupdate, so -d prints each request instead of being short for --dir.
+	/// This is the default when zonegen is run as `nsupdate`
+	#[arg(long)]
+	nsupdate_flags: bool,
+
+	/// Read the commands from this file instead of the standard input
+	filename: Option<PathBuf>,
+
+	#[command(flatten)]
+	nsupdate: NsupdateArgs,
+}
+
+/// Flags of `nsupdate`, so zonegen can be used in its place.
+/// Flags that only apply to sending updates to a DNS server are accepted but have no effect.
+/// `-d` is only the debug flag with `--nsupdate-flags` or when run as `nsupdate`, see `parse_args`.
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "nsupdate compatibility")]
+#[allow(clippy::struct_excessive_bools)] // Each flag is independent
+struct NsupdateArgs {
+	/// Print each request, -d with the flags of nsupdate
+	#[arg(long)]
+	debug: bool,
+
+	/// Print each request, like -d
+	#[arg(short = 'D')]
+	extra_debug: bool,
+
+	/// Print each request if the level is larger than 0
+	#[arg(short = 'L', value_name = "LEVEL", default_value_t = 0)]
+	debug_level: u8,
+
+	/// Read the commands interactively even if the standard input is not a terminal
+	#[arg(short = 'i', conflicts_with = "filename")]
+	interactive: bool,
+
+	/// File with the TSIG key, which has to be one of the keys in --keys
+	#[arg(short = 'k', value_name = "KEYFILE", conflicts_with = "key")]
+	key_file: Option<PathBuf>,
+
+	/// TSIG key, which has to be one of the keys in --keys
+	#[arg(short = 'y', value_name = "[HMAC:]KEYNAME:SECRET")]
+	key: Option<String>,
+
+	/// Local-host only mode, has no effect
+	#[arg(short = 'l')]
+	local: bool,
+
+	/// GSS-TSIG, has no effect
+	#[arg(short = 'g')]
+	gsstsig: bool,
+
+	/// Windows 2000 GSS-TSIG, has no effect
+	#[arg(short = 'o')]
+	oldgsstsig: bool,
+
+	/// Port of the DNS server, has no effect
+	#[arg(short = 'p', value_name = "PORT")]
+	port: Option<u16>,
+
+	/// Number of UDP retries, has no effect
+	#[arg(short = 'r', value_name = "UDPRETRIES")]
+	udp_retries: Option<u32>,
+
+	/// Timeout in seconds, has no effect
+	#[arg(short = 't', value_name = "TIMEOUT")]
+	timeout: Option<u32>,
+
+	/// UDP retry interval in seconds, has no effect
+	#[arg(short = 'u', value_name = "UDPTIMEOUT")]
+	udp_timeout: Option<u32>,
+
+	/// Use TCP, has no effect
+	#[arg(short = 'v')]
+	tcp: bool,
+
+	/// Print the list of record types, has no effect
+	#[arg(short = 'T')]
+	types: bool,
+
+	/// Print the list of private record types, has no effect
+	#[arg(short = 'P')]
+	private_types: bool,
+
+	/// Only use IPv4, has no effect
+	#[arg(short = '4', conflicts_with = "ipv6")]
+	ipv4: bool,
+
+	/// Only use IPv6, has no effect
+	#[arg(short = '6')]
+	ipv6: bool,
 }
 
-async fn run(args: Args) -> Result<()> {
+async fn run(args: Args) -> Result<bool> {
+	// Like nsupdate, refuse to run with a key that cannot be read
+	let key = match (&args.nsupdate.key_file, &args.nsupdate.key) {
+		(Some(key_file), _) => Some(tsig::Key::from_file(key_file)?),
+		(None, Some(key)) => Some(tsig::Key::from_argument(key)?),
+		(None, None) => None,
+	};
+	let allowed_keys = args.keys.as_deref().map(tsig::load).transpose()?;
+	if allowed_keys.is_none()
+		&& let Some(key) = &key
+	{
+		eprintln!(
+			"WARNING: the key `{}` is not checked because no allowed keys were given with --keys",
+			key.name()
+		);
+	}
+	let has_key = key.is_some();
+	let authorization = tsig::Authorization::new(allowed_keys, key);
+	// A wrong key on the command line is refused before anything is done, a missing one can still be given with the key command
+	if has_key {
+		authorization.check()?;
+	}
+	let debug = args.nsupdate.debug || args.nsupdate.extra_debug || args.nsupdate.debug_level > 0;
+
 	let pool = db::init(&args.dir).await?;
+	db::declare_zones(&pool, &args.zones).await?;
+	let psl = match (args.no_guess_zones, args.public_suffix_list) {
+		(true, _) => None,
+		(false, Some(path)) => Some(PublicSuffixList::load(&path)?),
+		(false, None) => Some(PublicSuffixList::embedded()),
+	};
 
-	repl(&pool).await?;
+	let success = match args.filename {
+		Some(filename) => {
+			let file = File::open(&filename)
+				.wrap_err_with(|| format!("Cannot open file `{}`", filename.display()))?;
+			repl::batch(
+				&pool,
+				psl.as_ref(),
+				debug,
+				authorization,
+				BufReader::new(file),
+			)
+			.await?
+		}
+		None if args.nsupdate.interactive || std::io::stdin().is_terminal() => {
+			repl::repl(
+				&pool,
+				psl.as_ref(),
+				debug,
+				authorization,
+				args.blank_line_sends,
+			)
+			.await?
+		}
+		None => {
+			repl::batch(
+				&pool,
+				psl.as_ref(),
+				debug,
+				authorization,
+				BufReader::new(std::io::stdin()),
+			)
+			.await?
+		}
+	};
 
 	db::save_zones(&pool, args.dir).await?;
 
-	Ok(())
+	Ok(success)
+}
+
+/// Like `Args::parse` but `-d` is the debug flag instead of being short for `--dir` when the flags of nsupdate are used
+fn parse_args() -> Args {
+	let mut raw_args = std::env::args_os();
+	let run_as_nsupdate = raw_args
+		.next()
+		.as_deref()
+		.map(Path::new)
+		.and_then(Path::file_name)
+		.is_some_and(|name| name == "nsupdate");
+	let nsupdate_flags = run_as_nsupdate || raw_args.any(|arg| arg == "--nsupdate-flags");
+
+	let mut command = Args::command();
+	if nsupdate_flags {
+		command = command
+			.mut_arg("dir", |arg| arg.short(None))
+			.mut_arg("debug", |arg| arg.short('d'));
+	}
+	Args::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit())
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
 	color_eyre::install()?;
 
-	let args = Args::parse();
-
-	run(args).await?;
+	let args = parse_args();
 
-	Ok(())
+	if run(args).await? {
+		Ok(ExitCode::SUCCESS)
+	} else {
+		Ok(ExitCode::FAILURE)
+	}
 }
@@ -1,165 +1,1213 @@
 // SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
 // SPDX-License-Identifier: GPL-3.0-only
 
-use nom::bytes::complete::take_while1;
+use crate::record::{self, Hex, RData, RecordType, ServiceBinding, SvcParam};
+use crate::zone_files;
+use nom::bytes::complete::{tag_no_case, take_while1};
+use nom::character::complete::{anychar, satisfy, space1};
+use nom::combinator::{cut, map_opt, map_res, opt, peek, rest, verify};
 use nom::error::context;
 use nom::lib::std::result::Result::Err;
+use nom::multi::{many0, many1, separated_list1};
+use nom::sequence::preceded;
 use nom::{AsChar, Err as NomErr, IResult, Parser, branch::alt, bytes::complete::tag};
 use nom_language::error::{VerboseError, VerboseErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 type Res<T, U> = IResult<T, U, VerboseError<T>>;
 
 #[derive(Debug, PartialEq, Eq)]
-pub enum Command<'a> {
+pub enum Command {
 	Help,
 	Send,
 	Quit,
 	Drop,
-	Update(Update<'a>),
+	Zone(Option<String>),
+	Origin(Option<String>),
+	List(Option<String>),
+	Ttl(Option<u32>),
+	Class(String),
+	Update(Update),
+	Prereq(Prereq),
+	Show,
+	Answer,
+	Debug,
+	Version,
+	/// TSIG key as `[hmac:]keyname` and the secret
+	Key {
+		name: String,
+		secret: String,
+	},
+	/// Command that only makes sense when talking to a DNS server, like `server` or `local`
+	Ignored(String),
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub enum Update<'a> {
-	Add(Add<'a>),
-	Delete(Delete<'a>),
+pub enum Update {
+	Add(Add),
+	Delete(Delete),
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub struct Add<'a> {
-	pub name: &'a str,
-	pub ttl: u32,
-	pub class: &'a str,
-	pub type_: &'a str,
-	pub data: &'a str,
+pub struct Add {
+	pub name: String,
+	/// Use the default TTL of the session if this is `None`
+	pub ttl: Option<u32>,
+	/// Use the default class of the session if this is `None`
+	pub class: Option<String>,
+	pub data: RData,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub struct Delete<'a> {
-	pub name: &'a str,
-	pub class: &'a str,
-	pub type_: &'a str,
+pub struct Delete {
+	pub name: String,
+	pub class: Option<String>,
+	/// Delete all RRsets of the name if this is `None`
+	pub type_: Option<RecordType>,
+	/// Delete the whole RRset if this is `None`
+	pub data: Option<RData>,
 }
 
-fn command(input: &str) -> Res<&str, Command<'_>> {
+/// Prerequisite of the pending update, see RFC 2136 section 2.4
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Condition {
+	/// The name must not own any records
+	Nxdomain,
+	/// The name must own at least one record
+	Yxdomain,
+	/// The RRset must not exist
+	Nxrrset,
+	/// The RRset must exist, with exactly the given records if there is data
+	Yxrrset,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Prereq {
+	pub condition: Condition,
+	pub name: String,
+	pub class: Option<String>,
+	/// `None` for the conditions about names and for the type ANY
+	pub type_: Option<RecordType>,
+	/// Only used by the value-dependent form of `Yxrrset`
+	pub data: Option<RData>,
+}
+
+fn command(input: &str) -> Res<&str, Command> {
 	context(
 		"command",
-		alt((help, send, quit, drop, update, add, delete)),
+		alt((
+			help,
+			send,
+			quit,
+			drop,
+			zone,
+			origin,
+			list,
+			default_ttl,
+			default_class,
+			update,
+			prereq,
+			show,
+			answer,
+			debug,
+			version,
+			key,
+			ignored,
+			add,
+			delete,
+		)),
 	)
 	.parse(input)
 }
 
-fn help(input: &str) -> Res<&str, Command<'_>> {
-	context("help", tag("help"))
+fn show(input: &str) -> Res<&str, Command> {
+	context("show", tag_no_case("show"))
+		.parse(input)
+		.map(|(next_input, _)| (next_input, Command::Show))
+}
+
+fn answer(input: &str) -> Res<&str, Command> {
+	context("answer", tag_no_case("answer"))
+		.parse(input)
+		.map(|(next_input, _)| (next_input, Command::Answer))
+}
+
+fn debug(input: &str) -> Res<&str, Command> {
+	context("debug", tag_no_case("debug"))
+		.parse(input)
+		.map(|(next_input, _)| (next_input, Command::Debug))
+}
+
+fn version(input: &str) -> Res<&str, Command> {
+	context("version", tag_no_case("version"))
+		.parse(input)
+		.map(|(next_input, _)| (next_input, Command::Version))
+}
+
+fn key(input: &str) -> Res<&str, Command> {
+	context(
+		"key",
+		preceded((tag_no_case("key"), space1), cut((field, space1, field))),
+	)
+	.parse(input)
+	.map(|(next_input, (name, _, secret))| {
+		(
+			next_input,
+			Command::Key {
+				name: name.to_string(),
+				secret: secret.to_string(),
+			},
+		)
+	})
+}
+
+fn ignored(input: &str) -> Res<&str, Command> {
+	context(
+		"ignored",
+		(
+			alt((
+				tag_no_case("server"),
+				tag_no_case("local"),
+				tag_no_case("gsstsig"),
+				tag_no_case("oldgsstsig"),
+				tag_no_case("realm"),
+				tag_no_case("lifetime"),
+				tag_no_case("check-names"),
+				tag_no_case("check-svcb"),
+			)),
+			// The arguments don't matter, they are ignored anyway
+			opt(preceded(space1, rest)),
+		),
+	)
+	.parse(input)
+	.map(|(next_input, (keyword, _))| (next_input, Command::Ignored(keyword.to_ascii_lowercase())))
+}
+
+fn help(input: &str) -> Res<&str, Command> {
+	context("help", tag_no_case("help"))
 		.parse(input)
 		.map(|(next_input, _)| (next_input, Command::Help))
 }
 
-fn send(input: &str) -> Res<&str, Command<'_>> {
-	context("send", tag("send"))
+fn send(input: &str) -> Res<&str, Command> {
+	context("send", tag_no_case("send"))
 		.parse(input)
 		.map(|(next_input, _)| (next_input, Command::Send))
 }
 
-fn quit(input: &str) -> Res<&str, Command<'_>> {
-	context("quit", tag("quit"))
+fn quit(input: &str) -> Res<&str, Command> {
+	context("quit", tag_no_case("quit"))
 		.parse(input)
 		.map(|(next_input, _)| (next_input, Command::Quit))
 }
 
-fn drop(input: &str) -> Res<&str, Command<'_>> {
-	context("drop", tag("drop"))
+fn drop(input: &str) -> Res<&str, Command> {
+	context("drop", tag_no_case("drop"))
 		.parse(input)
 		.map(|(next_input, _)| (next_input, Command::Drop))
 }
 
-fn update(input: &str) -> Res<&str, Command<'_>> {
-	context("update", (tag("update"), tag(" "), add_or_delete))
+fn zone(input: &str) -> Res<&str, Command> {
+	context("zone", (tag_no_case("zone"), opt(preceded(space1, name))))
+		.parse(input)
+		.map(|(next_input, (_, zone))| (next_input, Command::Zone(zone)))
+}
+
+fn origin(input: &str) -> Res<&str, Command> {
+	context(
+		"origin",
+		(tag_no_case("origin"), opt(preceded(space1, name))),
+	)
+	.parse(input)
+	.map(|(next_input, (_, origin))| (next_input, Command::Origin(origin)))
+}
+
+fn default_ttl(input: &str) -> Res<&str, Command> {
+	context(
+		"ttl",
+		(
+			(tag_no_case("ttl"), space1),
+			alt((tag_no_case("none").map(|_| None), ttl.map(Some))),
+		),
+	)
+	.parse(input)
+	.map(|(next_input, (_, ttl))| (next_input, Command::Ttl(ttl)))
+}
+
+fn default_class(input: &str) -> Res<&str, Command> {
+	context("class", preceded((tag_no_case("class"), space1), class))
+		.parse(input)
+		.map(|(next_input, class)| (next_input, Command::Class(class)))
+}
+
+fn list(input: &str) -> Res<&str, Command> {
+	context("list", (tag_no_case("list"), opt(preceded(space1, name))))
+		.parse(input)
+		.map(|(next_input, (_, zone))| (next_input, Command::List(zone)))
+}
+
+fn update(input: &str) -> Res<&str, Command> {
+	context("update", (tag_no_case("update"), space1, add_or_delete))
 		.parse(input)
 		.map(|(next_input, (_, _, command))| (next_input, command))
 }
 
-fn ttl(input: &str) -> Res<&str, u32> {
-	let digits = take_while1(AsChar::is_dec_digit);
-	context("ttl", digits)
+fn prereq(input: &str) -> Res<&str, Command> {
+	context(
+		"prereq",
+		preceded(
+			(tag_no_case("prereq"), space1),
+			alt((prereq_name, prereq_nxrrset, prereq_yxrrset)),
+		),
+	)
+	.parse(input)
+	.map(|(next_input, prereq)| (next_input, Command::Prereq(prereq)))
+}
+
+fn prereq_name(input: &str) -> Res<&str, Prereq> {
+	(
+		alt((
+			tag_no_case("nxdomain").map(|_| Condition::Nxdomain),
+			tag_no_case("yxdomain").map(|_| Condition::Yxdomain),
+		)),
+		space1,
+		owner,
+	)
+		.parse(input)
+		.map(|(next_input, (condition, _, name))| {
+			(
+				next_input,
+				Prereq {
+					condition,
+					name,
+					class: None,
+					type_: None,
+					data: None,
+				},
+			)
+		})
+}
+
+fn prereq_nxrrset(input: &str) -> Res<&str, Prereq> {
+	(
+		(tag_no_case("nxrrset"), space1),
+		owner,
+		opt(preceded(space1, class)),
+		space1,
+		record_type_or_any,
+	)
+		.parse(input)
+		.map(|(next_input, (_, name, class, _, type_))| {
+			(
+				next_input,
+				Prereq {
+					condition: Condition::Nxrrset,
+					name,
+					class,
+					type_,
+					data: None,
+				},
+			)
+		})
+}
+
+fn prereq_yxrrset(input: &str) -> Res<&str, Prereq> {
+	(
+		(tag_no_case("yxrrset"), space1),
+		owner,
+		opt(preceded(space1, class)),
+		space1,
+		type_and_optional_data,
+	)
 		.parse(input)
-		.and_then(|(next_input, res)| {
-			res.parse::<u32>().map_or_else(
-				|_| Err(NomErr::Error(VerboseError { errors: vec![] })),
-				|n| Ok((next_input, n)),
+		.map(|(next_input, (_, name, class, _, (type_, data)))| {
+			(
+				next_input,
+				Prereq {
+					condition: Condition::Yxrrset,
+					name,
+					class,
+					type_,
+					data,
+				},
 			)
 		})
 }
 
-fn add(input: &str) -> Res<&str, Command<'_>> {
-	let name =
-		take_while1(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.');
-	let class = take_while1(|c: char| c.is_ascii_uppercase());
-	let type_ = take_while1(|c: char| c.is_ascii_uppercase());
-	let data = take_while1(|c: char| c.is_ascii_graphic());
+/// Longest name without the trailing dot, so that it takes at most 255 bytes on the wire (RFC 1035 section 2.3.4)
+pub const MAX_NAME_LENGTH: usize = 253;
+
+fn name(input: &str) -> Res<&str, String> {
+	let (next_input, name) = context(
+		"name",
+		map_res(
+			// Underscores are needed for names like _acme-challenge, _dmarc or _sip._tcp
+			take_while1(|c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == '.'),
+			to_ascii_name,
+		),
+	)
+	.parse(input)?;
+	// BIND refuses to load a zone with any of these names
+	if name != "." {
+		let relative = name.strip_suffix('.').unwrap_or(&name);
+		let labels: Vec<&str> = relative.split('.').collect();
+		if labels.iter().any(|label| label.is_empty()) {
+			return error(input, "name without empty labels");
+		}
+		if labels.iter().any(|label| label.len() > 63) {
+			return error(input, "labels of at most 63 characters");
+		}
+		if labels
+			.iter()
+			.any(|label| label.starts_with('-') || label.ends_with('-'))
+		{
+			return error(input, "labels that do not start or end with a hyphen");
+		}
+		if relative.len() > MAX_NAME_LENGTH {
+			return error(input, "name of at most 253 characters");
+		}
+	}
+	Ok((next_input, name))
+}
+
+/// Name given outside of a command, like a zone on the command line, converted and checked like any other name
+pub fn parse_name(input: &str) -> Option<String> {
+	match name(input) {
+		Ok(("", name)) => Some(name),
+		_ => None,
+	}
+}
+
+/// Convert internationalized names to A-labels (RFC 5891), so only ASCII reaches the database and the zone files
+fn to_ascii_name(name: &str) -> Result<String, idna::Errors> {
+	if name.is_ascii() {
+		// Names are case-insensitive, so always store them in lowercase
+		return Ok(name.to_ascii_lowercase());
+	}
+	idna::domain_to_ascii(name)
+}
+
+/// Name of a record, `@` stands for the origin like in zone files
+fn owner(input: &str) -> Res<&str, String> {
+	alt((tag("@").map(String::from), wildcard, name)).parse(input)
+}
+
+/// Wildcard name like `*.example.org.`, the `*` is only special as the first label (RFC 4592)
+fn wildcard(input: &str) -> Res<&str, String> {
+	context("wildcard", (tag("*"), opt(preceded(tag("."), opt(name)))))
+		.parse(input)
+		.map(|(next_input, (_, rest))| {
+			let name = match rest {
+				None => "*".to_string(),
+				Some(None) => "*.".to_string(),
+				Some(Some(name)) => format!("*.{name}"),
+			};
+			(next_input, name)
+		})
+}
+
+/// Largest TTL allowed by RFC 2181 section 8
+const MAX_TTL: u64 = 2_147_483_647;
+
+/// TTL in seconds, optionally with BIND-style units like `1h30m` or `1w`
+fn ttl(input: &str) -> Res<&str, u32> {
+	let unit = satisfy(|c| "wdhmsWDHMS".contains(c));
+	let (next_input, parts) =
+		context("ttl", many1((take_while1(AsChar::is_dec_digit), opt(unit)))).parse(input)?;
+	let mut seconds: u64 = 0;
+	for (digits, unit) in parts {
+		let multiplier = match unit.map(|unit| unit.to_ascii_lowercase()) {
+			Some('w') => 7 * 24 * 60 * 60,
+			Some('d') => 24 * 60 * 60,
+			Some('h') => 60 * 60,
+			Some('m') => 60,
+			_ => 1,
+		};
+		seconds = digits
+			.parse::<u64>()
+			.ok()
+			.and_then(|value| value.checked_mul(multiplier))
+			.and_then(|value| value.checked_add(seconds))
+			.unwrap_or(u64::MAX);
+	}
+	match u32::try_from(seconds) {
+		Ok(seconds) if u64::from(seconds) <= MAX_TTL => Ok((next_input, seconds)),
+		// Fail instead of trying to read the number as something else
+		_ => Err(NomErr::Failure(VerboseError {
+			errors: vec![(
+				input,
+				VerboseErrorKind::Context("TTL must not be larger than 2147483647 seconds"),
+			)],
+		})),
+	}
+}
+
+fn add(input: &str) -> Res<&str, Command> {
 	context(
 		"add",
 		(
-			tag("add"),
-			tag(" "),
-			name,
-			tag(" "),
-			ttl,
-			tag(" "),
-			class,
-			tag(" "),
-			type_,
-			tag(" "),
-			data,
+			tag_no_case("add"),
+			space1,
+			owner,
+			opt(preceded(space1, ttl)),
+			opt(preceded(space1, class)),
+			space1,
+			type_and_data,
 		),
 	)
 	.parse(input)
-	.map(
-		|(next_input, (_, _, name, _, ttl, _, class, _, type_, _, data))| {
+	.map(|(next_input, (_, _, name, ttl, class, _, data))| {
+		(
+			next_input,
+			Command::Update(Update::Add(Add {
+				name,
+				ttl,
+				class,
+				data,
+			})),
+		)
+	})
+}
+
+fn error<'a, T>(input: &'a str, context: &'static str) -> Res<&'a str, T> {
+	Err(NomErr::Error(VerboseError {
+		errors: vec![(input, VerboseErrorKind::Context(context))],
+	}))
+}
+
+/// A single <character-string> as described in RFC 1035 section 5.1, either quoted or not
+fn character_string(input: &str) -> Res<&str, Vec<u8>> {
+	let (quoted, mut rest) = input
+		.strip_prefix('"')
+		.map_or((false, input), |rest| (true, rest));
+	let mut bytes = Vec::new();
+	loop {
+		let mut chars = rest.chars();
+		match (quoted, chars.next()) {
+			(true, Some('"')) => return Ok((chars.as_str(), bytes)),
+			(true, None) => return error(input, "closing quote"),
+			(false, Some('"')) => return error(rest, "quote in unquoted character string"),
+			(false, Some(' ' | '\t') | None) => {
+				if bytes.is_empty() {
+					return error(rest, "character string");
+				}
+				return Ok((rest, bytes));
+			}
+			(_, Some('\\')) => {
+				let escaped = chars.as_str();
+				if let Some(digits) = escaped
+					.get(..3)
+					.filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+				{
+					// \DDD is the byte with the decimal value DDD
+					let Ok(byte) = digits.parse::<u8>() else {
+						return error(rest, "escaped byte value");
+					};
+					bytes.push(byte);
+					rest = &escaped[3..];
+				} else {
+					// Any other escaped character stands for itself
+					let Some(c) = chars.next() else {
+						return error(rest, "escaped character");
+					};
+					bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+					rest = chars.as_str();
+				}
+			}
+			(_, Some(c)) => {
+				bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+				rest = chars.as_str();
+			}
+		}
+	}
+}
+
+fn character_strings(input: &str) -> Res<&str, Vec<Vec<u8>>> {
+	context(
+		"character strings",
+		separated_list1(space1, character_string),
+	)
+	.parse(input)
+	.map(|(next_input, strings)| {
+		// A character string can be at most 255 bytes long, so longer values are split
+		let strings = strings
+			.iter()
+			.flat_map(|string| {
+				if string.is_empty() {
+					vec![string.as_slice()]
+				} else {
+					string.chunks(255).collect()
+				}
+			})
+			.map(<[u8]>::to_vec)
+			.collect();
+		(next_input, strings)
+	})
+}
+
+/// Domain name inside of rdata, which may be internationalized like an owner name
+fn target(input: &str) -> Res<&str, String> {
+	context("domain name", alt((tag("@").map(String::from), name))).parse(input)
+}
+
+/// Field of the data that is neither a domain name nor a character string, like an address or a number
+fn field(input: &str) -> Res<&str, &str> {
+	take_while1(|c: char| c.is_ascii_graphic()).parse(input)
+}
+
+fn mx(input: &str) -> Res<&str, RData> {
+	(
+		context("preference", map_res(field, str::parse)),
+		space1,
+		target,
+	)
+		.parse(input)
+		.map(|(next_input, (preference, _, exchange))| {
 			(
 				next_input,
-				Command::Update(Update::Add(Add {
-					name,
-					ttl,
-					class,
-					type_,
-					data,
-				})),
+				RData::Mx {
+					preference,
+					exchange,
+				},
+			)
+		})
+}
+
+fn srv(input: &str) -> Res<&str, RData> {
+	(
+		context("priority", map_res(field, str::parse)),
+		space1,
+		context("weight", map_res(field, str::parse)),
+		space1,
+		context("port", map_res(field, str::parse)),
+		space1,
+		target,
+	)
+		.parse(input)
+		.map(|(next_input, (priority, _, weight, _, port, _, target))| {
+			(
+				next_input,
+				RData::Srv {
+					priority,
+					weight,
+					port,
+					target,
+				},
 			)
+		})
+}
+
+fn caa(input: &str) -> Res<&str, RData> {
+	(
+		context("flags", map_res(field, str::parse)),
+		space1,
+		// Tags are case-insensitive and at most 15 characters long (RFC 8659 section 4.1)
+		context(
+			"CAA tag",
+			verify(
+				take_while1(|c: char| c.is_ascii_alphanumeric()),
+				|tag: &str| tag.len() <= 15,
+			),
+		),
+		space1,
+		context("CAA value", character_string),
+	)
+		.parse(input)
+		.map(|(next_input, (flags, _, tag, _, value))| {
+			(
+				next_input,
+				RData::Caa {
+					flags,
+					tag: tag.to_ascii_lowercase(),
+					value,
+				},
+			)
+		})
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+	if !hex.len().is_multiple_of(2) {
+		return None;
+	}
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+		.collect()
+}
+
+/// Hexadecimal data, which may be split into several parts by whitespace
+fn hex_data(input: &str) -> Res<&str, Vec<u8>> {
+	context(
+		"hexadecimal data",
+		map_opt(
+			separated_list1(space1, take_while1(|c: char| c.is_ascii_hexdigit())),
+			|parts: Vec<&str>| decode_hex(&parts.concat()),
+		),
+	)
+	.parse(input)
+}
+
+fn sshfp(input: &str) -> Res<&str, RData> {
+	let (input, (algorithm, _, fingerprint_type, _)) = (
+		// RSA, DSA, ECDSA, Ed25519 and Ed448 (RFC 4255, RFC 6594, RFC 7479, RFC 8709)
+		context(
+			"algorithm",
+			verify(map_res(field, str::parse), |algorithm: &u8| {
+				matches!(algorithm, 1..=4 | 6)
+			}),
+		),
+		space1,
+		context(
+			"fingerprint type",
+			verify(map_res(field, str::parse), |type_: &u8| {
+				matches!(type_, 1 | 2)
+			}),
+		),
+		space1,
+	)
+		.parse(input)?;
+	let (next_input, fingerprint) = hex_data(input)?;
+	// SHA-1 or SHA-256
+	let length = if fingerprint_type == 1 { 20 } else { 32 };
+	if fingerprint.len() != length {
+		return error(input, "fingerprint length");
+	}
+	Ok((
+		next_input,
+		RData::Sshfp {
+			algorithm,
+			fingerprint_type,
+			fingerprint,
 		},
+	))
+}
+
+fn tlsa(input: &str) -> Res<&str, RData> {
+	let (input, (usage, _, selector, _, matching_type, _)) = (
+		context(
+			"certificate usage",
+			verify(map_res(field, str::parse), |usage: &u8| *usage <= 3),
+		),
+		space1,
+		context(
+			"selector",
+			verify(map_res(field, str::parse), |selector: &u8| *selector <= 1),
+		),
+		space1,
+		context(
+			"matching type",
+			verify(map_res(field, str::parse), |type_: &u8| *type_ <= 2),
+		),
+		space1,
 	)
+		.parse(input)?;
+	let (next_input, data) = hex_data(input)?;
+	// The full data, SHA-256 or SHA-512
+	let valid = match matching_type {
+		0 => !data.is_empty(),
+		1 => data.len() == 32,
+		_ => data.len() == 64,
+	};
+	if !valid {
+		return error(input, "certificate association data length");
+	}
+	Ok((
+		next_input,
+		RData::Tlsa {
+			usage,
+			selector,
+			matching_type,
+			data,
+		},
+	))
 }
 
-fn delete(input: &str) -> Res<&str, Command<'_>> {
-	let name =
-		take_while1(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.');
-	let class = take_while1(|c: char| c.is_ascii_uppercase());
-	let type_ = take_while1(|c: char| c.is_ascii_uppercase());
+fn is_base64(input: &str) -> bool {
+	let data = input.trim_end_matches('=');
+	!input.is_empty()
+		&& input.len().is_multiple_of(4)
+		&& input.len() - data.len() <= 2
+		&& data
+			.bytes()
+			.all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
+/// Value of a SvcParam (RFC 9460 section 7), `None` if it is not valid for the key
+fn svc_param_value(key: u16, value: Option<Vec<u8>>) -> Option<SvcParam> {
+	let value = match (key, value) {
+		(2, None) => return Some(SvcParam::NoDefaultAlpn),
+		(7.., value) => {
+			return Some(SvcParam::Unknown {
+				key,
+				value: value.unwrap_or_default(),
+			});
+		}
+		(_, None) => return None,
+		(_, Some(value)) => String::from_utf8(value).ok()?,
+	};
+	let list = value.split(',');
+	match key {
+		0 => {
+			let keys = list
+				.map(SvcParam::parse_key)
+				.collect::<Option<Vec<u16>>>()?;
+			// mandatory must not list itself
+			(!keys.contains(&0)).then_some(SvcParam::Mandatory(keys))
+		}
+		1 => {
+			let ids: Vec<String> = list.map(String::from).collect();
+			// Only IDs that can be written without quotes or escapes, which is true for every registered one
+			ids.iter()
+				.all(|id| {
+					!id.is_empty()
+						&& id.len() <= 255 && id
+						.bytes()
+						.all(|b| b.is_ascii_graphic() && !b"\"\\;()".contains(&b))
+				})
+				.then_some(SvcParam::Alpn(ids))
+		}
+		3 => value.parse().ok().map(SvcParam::Port),
+		4 => list
+			.map(|address| address.parse().ok())
+			.collect::<Option<_>>()
+			.map(SvcParam::Ipv4Hint),
+		5 => is_base64(&value).then_some(SvcParam::Ech(value)),
+		6 => list
+			.map(|address| address.parse().ok())
+			.collect::<Option<_>>()
+			.map(SvcParam::Ipv6Hint),
+		_ => None,
+	}
+}
+
+fn svc_param(input: &str) -> Res<&str, SvcParam> {
+	context(
+		"SvcParam",
+		map_opt(
+			(
+				context(
+					"SvcParam key",
+					map_opt(
+						take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-'),
+						SvcParam::parse_key,
+					),
+				),
+				opt(preceded(tag("="), character_string)),
+			),
+			|(key, value)| svc_param_value(key, value),
+		),
+	)
+	.parse(input)
+}
+
+/// Data of SVCB and HTTPS records (RFC 9460 section 2.1)
+fn service_binding(input: &str) -> Res<&str, ServiceBinding> {
+	let (next_input, (priority, _, target, mut params)) = (
+		context("priority", map_res(field, str::parse)),
+		space1,
+		target,
+		many0(preceded(space1, cut(svc_param))),
+	)
+		.parse(input)?;
+	params.sort_by_key(SvcParam::key);
+	let has = |key| params.iter().any(|param| param.key() == key);
+	if priority == 0 && !params.is_empty() {
+		return error(input, "no SvcParams in alias mode");
+	}
+	if params.windows(2).any(|pair| pair[0].key() == pair[1].key()) {
+		return error(input, "unique SvcParam keys");
+	}
+	if let Some(SvcParam::Mandatory(keys)) = params.first()
+		&& !keys.iter().all(|key| has(*key))
+	{
+		return error(input, "SvcParams listed as mandatory");
+	}
+	if has(2) && !has(1) {
+		return error(input, "alpn together with no-default-alpn");
+	}
+	Ok((
+		next_input,
+		ServiceBinding {
+			priority,
+			target,
+			params,
+		},
+	))
+}
+
+/// Data in the generic format `\# <length> <hex>` (RFC 3597 section 5)
+fn generic_data(input: &str) -> Res<&str, Vec<u8>> {
+	let (input, (_, _, length)) = (
+		tag("\\#"),
+		space1,
+		context("data length", map_res(field, str::parse::<usize>)),
+	)
+		.parse(input)?;
+	let (next_input, data) = opt(preceded(space1, hex_data)).parse(input)?;
+	let data = data.unwrap_or_default();
+	if data.len() != length {
+		return error(input, "data of the given length");
+	}
+	Ok((next_input, data))
+}
+
+/// Reader for data in the wire format, which is given in the generic format
+struct Wire<'a>(&'a [u8]);
+
+impl<'a> Wire<'a> {
+	const fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+		let taken = self.0.get(..length)?;
+		self.0 = &self.0[length..];
+		Some(taken)
+	}
+
+	fn rest(&mut self) -> &'a [u8] {
+		std::mem::take(&mut self.0)
+	}
+
+	fn u8(&mut self) -> Option<u8> {
+		self.take(1).map(|bytes| bytes[0])
+	}
+
+	fn u16(&mut self) -> Option<u16> {
+		self.take(2)
+			.map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+	}
+
+	/// Uncompressed domain name (RFC 3597 section 4) in the presentation format, always absolute
+	fn name(&mut self) -> Option<String> {
+		let mut name = String::new();
+		loop {
+			let label = self
+				.u8()
+				.and_then(|length| self.take(usize::from(length)))?;
+			if label.is_empty() {
+				break;
+			}
+			// Other characters would need escapes, which are not supported in names
+			if !label
+				.iter()
+				.all(|b| b.is_ascii_alphanumeric() || *b == b'-' || *b == b'_')
+			{
+				return None;
+			}
+			name.push_str(std::str::from_utf8(label).ok()?);
+			name.push('.');
+		}
+		Some(if name.is_empty() {
+			".".to_string()
+		} else {
+			name
+		})
+	}
+
+	fn character_string(&mut self) -> Option<String> {
+		self.u8()
+			.and_then(|length| self.take(usize::from(length)))
+			.map(zone_files::quote_character_string)
+	}
+
+	/// The remaining data split into parts of the same length
+	fn chunks<const N: usize>(&mut self) -> Option<Vec<[u8; N]>> {
+		let rest = self.rest();
+		if rest.is_empty() || !rest.len().is_multiple_of(N) {
+			return None;
+		}
+		rest.chunks(N).map(|chunk| chunk.try_into().ok()).collect()
+	}
+}
+
+fn base64(bytes: &[u8]) -> String {
+	const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut encoded = String::new();
+	for chunk in bytes.chunks(3) {
+		let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+			group | u32::from(*byte) << (16 - 8 * i)
+		});
+		for i in 0..4 {
+			if i <= chunk.len() {
+				encoded.push(char::from(
+					ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize],
+				));
+			} else {
+				encoded.push('=');
+			}
+		}
+	}
+	encoded
+}
+
+/// SvcParam in the presentation format from its wire format (RFC 9460 section 7)
+fn svc_param_presentation(key: u16, mut value: Wire) -> Option<String> {
+	let name = SvcParam::key_name(key);
+	let text = match key {
+		0 => {
+			let mut keys = Vec::new();
+			while !value.is_empty() {
+				keys.push(SvcParam::key_name(value.u16()?));
+			}
+			keys.join(",")
+		}
+		1 => {
+			let mut ids = Vec::new();
+			while !value.is_empty() {
+				let id = value
+					.u8()
+					.and_then(|length| value.take(usize::from(length)))?;
+				// Commas and quotes would need escapes, which are not supported in the presentation format
+				if !id
+					.iter()
+					.all(|b| b.is_ascii_graphic() && !b",\"\\".contains(b))
+				{
+					return None;
+				}
+				ids.push(std::str::from_utf8(id).ok()?.to_string());
+			}
+			ids.join(",")
+		}
+		2 => return value.is_empty().then_some(name),
+		3 => value.u16()?.to_string(),
+		4 => join_addresses(value.chunks::<4>()?.into_iter().map(Ipv4Addr::from)),
+		5 => base64(value.rest()),
+		6 => join_addresses(value.chunks::<16>()?.into_iter().map(Ipv6Addr::from)),
+		_ if value.is_empty() => return Some(name),
+		_ => zone_files::quote_character_string(value.rest()),
+	};
+	value.is_empty().then(|| format!("{name}={text}"))
+}
+
+fn join_addresses<T: ToString>(addresses: impl Iterator<Item = T>) -> String {
+	addresses
+		.map(|address| address.to_string())
+		.collect::<Vec<_>>()
+		.join(",")
+}
+
+/// Presentation format of data of a known type that was given in the generic format (RFC 3597 section 5).
+/// It is parsed afterwards, so it is checked just like data in the presentation format.
+fn wire_to_presentation(type_: RecordType, data: &[u8]) -> Option<String> {
+	let mut wire = Wire(data);
+	let text = match type_ {
+		RecordType::A => Ipv4Addr::from(<[u8; 4]>::try_from(wire.rest()).ok()?).to_string(),
+		RecordType::Aaaa => Ipv6Addr::from(<[u8; 16]>::try_from(wire.rest()).ok()?).to_string(),
+		RecordType::Caa => {
+			let flags = wire.u8()?;
+			let tag = wire
+				.u8()
+				.and_then(|length| wire.take(usize::from(length)))?;
+			format!(
+				"{flags} {} {}",
+				std::str::from_utf8(tag).ok()?,
+				zone_files::quote_character_string(wire.rest())
+			)
+		}
+		RecordType::Cname | RecordType::Dname | RecordType::Ns | RecordType::Ptr => wire.name()?,
+		RecordType::Mx => format!("{} {}", wire.u16()?, wire.name()?),
+		RecordType::Spf | RecordType::Txt => {
+			let mut strings = Vec::new();
+			while !wire.is_empty() {
+				strings.push(wire.character_string()?);
+			}
+			strings.join(" ")
+		}
+		RecordType::Srv => format!(
+			"{} {} {} {}",
+			wire.u16()?,
+			wire.u16()?,
+			wire.u16()?,
+			wire.name()?
+		),
+		RecordType::Sshfp => format!("{} {} {}", wire.u8()?, wire.u8()?, Hex(wire.rest())),
+		RecordType::Tlsa => format!(
+			"{} {} {} {}",
+			wire.u8()?,
+			wire.u8()?,
+			wire.u8()?,
+			Hex(wire.rest())
+		),
+		RecordType::Https | RecordType::Svcb => {
+			let mut text = format!("{} {}", wire.u16()?, wire.name()?);
+			while !wire.is_empty() {
+				let key = wire.u16()?;
+				let value = wire
+					.u16()
+					.and_then(|length| wire.take(usize::from(length)))?;
+				text.push(' ');
+				text.push_str(&svc_param_presentation(key, Wire(value))?);
+			}
+			text
+		}
+		RecordType::Unknown(_) => return None,
+	};
+	wire.is_empty().then_some(text)
+}
+
+/// Data of a known type in the generic format, which is converted to the typed data
+fn known_generic_data(type_: RecordType, input: &str) -> Res<&str, RData> {
+	let (next_input, data) = context("generic data", generic_data).parse(input)?;
+	let rdata = wire_to_presentation(type_, &data).and_then(|text| match rdata(type_, &text) {
+		Ok(("", rdata)) => Some(rdata),
+		_ => None,
+	});
+	rdata.map_or_else(
+		|| error(input, "data in the wire format of the type"),
+		|rdata| Ok((next_input, rdata)),
+	)
+}
+
+fn rdata(type_: RecordType, input: &str) -> Res<&str, RData> {
+	if input.starts_with("\\#") && !matches!(type_, RecordType::Unknown(_)) {
+		return known_generic_data(type_, input);
+	}
+	match type_ {
+		RecordType::A => context("IPv4 address", map_res(field, str::parse))
+			.map(RData::A)
+			.parse(input),
+		RecordType::Aaaa => context("IPv6 address", map_res(field, str::parse))
+			.map(RData::Aaaa)
+			.parse(input),
+		RecordType::Caa => context("CAA data", caa).parse(input),
+		RecordType::Cname => target.map(RData::Cname).parse(input),
+		RecordType::Dname => target.map(RData::Dname).parse(input),
+		RecordType::Https => context("HTTPS data", service_binding)
+			.map(RData::Https)
+			.parse(input),
+		RecordType::Mx => context("MX data", mx).parse(input),
+		RecordType::Ns => target.map(RData::Ns).parse(input),
+		RecordType::Ptr => target.map(RData::Ptr).parse(input),
+		RecordType::Spf => character_strings.map(RData::Spf).parse(input),
+		RecordType::Srv => context("SRV data", srv).parse(input),
+		RecordType::Sshfp => context("SSHFP data", sshfp).parse(input),
+		RecordType::Svcb => context("SVCB data", service_binding)
+			.map(RData::Svcb)
+			.parse(input),
+		RecordType::Tlsa => context("TLSA data", tlsa).parse(input),
+		RecordType::Unknown(type_) => context("generic data", generic_data)
+			.map(|data| RData::Unknown { type_, data })
+			.parse(input),
+		RecordType::Txt => character_strings.map(RData::Txt).parse(input),
+	}
+}
+
+/// Parse data as it is stored in the database, `None` if it is not valid for the type
+pub fn stored_rdata(type_: &str, data: &str) -> Option<RData> {
+	match rdata(type_.parse().ok()?, data) {
+		Ok(("", rdata)) => Some(rdata),
+		_ => None,
+	}
+}
+
+fn record_type(input: &str) -> Res<&str, RecordType> {
+	context(
+		"record type",
+		map_res(take_while1(|c: char| c.is_ascii_alphanumeric()), str::parse),
+	)
+	.parse(input)
+}
+
+/// Record type, or `None` for the type ANY which matches every type, like in nsupdate
+fn record_type_or_any(input: &str) -> Res<&str, Option<RecordType>> {
+	alt((
+		verify(
+			take_while1(|c: char| c.is_ascii_alphanumeric()),
+			|type_: &str| type_.eq_ignore_ascii_case("ANY"),
+		)
+		.map(|_| None),
+		record_type.map(Some),
+	))
+	.parse(input)
+}
+
+fn type_and_data(input: &str) -> Res<&str, RData> {
+	let (input, (type_, _)) = (record_type, space1).parse(input)?;
+	// Once the type is known, malformed data is reported as such instead of trying other commands
+	cut(|input| rdata(type_, input)).parse(input)
+}
+
+fn type_and_optional_data(input: &str) -> Res<&str, (Option<RecordType>, Option<RData>)> {
+	let (input, type_) = record_type_or_any(input)?;
+	let Some(type_) = type_ else {
+		return Ok((input, (None, None)));
+	};
+	let (input, data) = opt(preceded(
+		(space1, peek(anychar)),
+		cut(|input| rdata(type_, input)),
+	))
+	.parse(input)?;
+	Ok((input, (Some(type_), data)))
+}
+
+/// Class given as `CLASSnnn` (RFC 3597 section 5), known classes are converted to their mnemonic
+fn generic_class(input: &str) -> Res<&str, String> {
+	map_opt(
+		preceded(
+			tag_no_case("CLASS"),
+			take_while1(|c: char| c.is_ascii_digit()),
+		),
+		|digits| match record::parse_code(digits)? {
+			1 => Some("IN".to_string()),
+			3 => Some("CH".to_string()),
+			4 => Some("HS".to_string()),
+			// Reserved and the QCLASSes NONE and ANY (RFC 6895 section 3.2)
+			0 | 254 | 255 | 65535 => None,
+			code => Some(format!("CLASS{code}")),
+		},
+	)
+	.parse(input)
+}
+
+fn class(input: &str) -> Res<&str, String> {
+	context(
+		"class",
+		alt((
+			generic_class,
+			verify(
+				take_while1(|c: char| c.is_ascii_alphabetic())
+					.map(|class: &str| class.to_ascii_uppercase()),
+				|class: &str| ["IN", "CH", "HS"].contains(&class),
+			),
+		)),
+	)
+	.parse(input)
+}
+
+fn delete(input: &str) -> Res<&str, Command> {
 	context(
 		"delete",
 		(
-			alt((tag("delete"), tag("del"))),
-			tag(" "),
-			name,
-			tag(" "),
-			class,
-			tag(" "),
-			type_,
+			alt((tag_no_case("delete"), tag_no_case("del"))),
+			space1,
+			owner,
+			// The TTL is ignored when deleting, like in nsupdate
+			opt(preceded(space1, ttl)),
+			opt(preceded(space1, class)),
+			opt(preceded(space1, type_and_optional_data)),
 		),
 	)
 	.parse(input)
-	.map(|(next_input, (_, _, name, _, class, _, type_))| {
+	.map(|(next_input, (_, _, name, _, class, type_and_data))| {
+		let (type_, data) = type_and_data.unwrap_or_default();
 		(
 			next_input,
-			Command::Update(Update::Delete(Delete { name, class, type_ })),
+			Command::Update(Update::Delete(Delete {
+				name,
+				class,
+				type_,
+				data,
+			})),
 		)
 	})
 }
 
-fn add_or_delete(input: &str) -> Res<&str, Command<'_>> {
+fn add_or_delete(input: &str) -> Res<&str, Command> {
 	context("add or delete", alt((add, delete))).parse(input)
 }
 
-pub fn parse(input: &str) -> Result<Command<'_>, NomErr<VerboseError<&str>>> {
+pub fn parse(input: &str) -> Result<Command, NomErr<VerboseError<&str>>> {
 	let res = command(input);
 	match res {
 		Ok(("", command)) => Ok(command),
@@ -175,10 +1223,15 @@ pub fn parse(input: &str) -> Result<Command<'_>, NomErr<VerboseError<&str>>> {
 
 #[cfg(test)]
 mod test {
-	use super::{Add, Command, Delete, Update, add, command, delete, parse};
+	use super::{
+		Add, Command, Condition, Delete, Prereq, Update, add, command, delete, owner, parse,
+		parse_name, stored_rdata, ttl,
+	};
+	use crate::record::{RData, RecordType, ServiceBinding, SvcParam};
 	use nom::Err as NomErr;
 	use nom::error::ErrorKind;
 	use nom_language::error::{VerboseError, VerboseErrorKind};
+	use std::net::{Ipv4Addr, Ipv6Addr};
 
 	#[test]
 	fn command_token_test() {
@@ -186,6 +1239,45 @@ mod test {
 		assert_eq!(command("send"), Ok(("", Command::Send)));
 		assert_eq!(command("quit"), Ok(("", Command::Quit)));
 		assert_eq!(command("drop"), Ok(("", Command::Drop)));
+		assert_eq!(
+			command("zone example.org."),
+			Ok(("", Command::Zone(Some("example.org.".to_string()))))
+		);
+		assert_eq!(command("zone"), Ok(("", Command::Zone(None))));
+		assert_eq!(command("list"), Ok(("", Command::List(None))));
+		assert_eq!(command("show"), Ok(("", Command::Show)));
+		assert_eq!(command("answer"), Ok(("", Command::Answer)));
+		assert_eq!(command("debug"), Ok(("", Command::Debug)));
+		assert_eq!(command("version"), Ok(("", Command::Version)));
+		assert_eq!(
+			command("server 127.0.0.1 53"),
+			Ok(("", Command::Ignored("server".to_string())))
+		);
+		assert_eq!(
+			command("key hmac-sha256:name c2VjcmV0"),
+			Ok((
+				"",
+				Command::Key {
+					name: "hmac-sha256:name".to_string(),
+					secret: "c2VjcmV0".to_string(),
+				}
+			))
+		);
+		assert!(parse("key name").is_err());
+		assert_eq!(
+			command("gsstsig"),
+			Ok(("", Command::Ignored("gsstsig".to_string())))
+		);
+		assert_eq!(command("ttl 300"), Ok(("", Command::Ttl(Some(300)))));
+		assert_eq!(command("TTL none"), Ok(("", Command::Ttl(None))));
+		assert_eq!(
+			command("class ch"),
+			Ok(("", Command::Class("CH".to_string())))
+		);
+		assert_eq!(
+			command("origin example.org."),
+			Ok(("", Command::Origin(Some("example.org.".to_string()))))
+		);
 		assert_eq!(
 			command("update bla"),
 			Err(NomErr::Error(VerboseError {
@@ -214,35 +1306,598 @@ mod test {
 
 	#[test]
 	fn add_test() {
+		assert_eq!(
+			add("add test.example.org. A 192.0.2.1"),
+			Ok((
+				"",
+				Command::Update(Update::Add(Add {
+					name: "test.example.org.".to_string(),
+					ttl: None,
+					class: None,
+					data: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+				}))
+			))
+		);
+		assert_eq!(
+			add("add test.example.org. 300 A 192.0.2.1"),
+			Ok((
+				"",
+				Command::Update(Update::Add(Add {
+					name: "test.example.org.".to_string(),
+					ttl: Some(300),
+					class: None,
+					data: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+				}))
+			))
+		);
+		assert_eq!(
+			add("add @ 300 IN A 192.0.2.1"),
+			Ok((
+				"",
+				Command::Update(Update::Add(Add {
+					name: "@".to_string(),
+					ttl: Some(300),
+					class: Some("IN".to_string()),
+					data: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+				}))
+			))
+		);
 		assert_eq!(
 			add("add test.example.org. 300 IN AAAA ::1"),
 			Ok((
 				"",
 				Command::Update(Update::Add(Add {
-					name: "test.example.org.",
-					ttl: 300,
-					class: "IN",
-					type_: "AAAA",
-					data: "::1",
+					name: "test.example.org.".to_string(),
+					ttl: Some(300),
+					class: Some("IN".to_string()),
+					data: RData::Aaaa(Ipv6Addr::LOCALHOST),
 				}))
 			))
 		);
+		// Without a valid TTL, f300 is taken as the type and the rest of the line is left over
+		assert!(parse("add test.example.org. f300 IN AAAA ::1").is_err());
+	}
+
+	#[test]
+	fn rdata_test() {
+		let context = |line: &str| match parse(line) {
+			Err(NomErr::Error(e) | NomErr::Failure(e)) => {
+				e.errors.iter().find_map(|(_, kind)| match kind {
+					VerboseErrorKind::Context(context) => Some(*context),
+					_ => None,
+				})
+			}
+			_ => None,
+		};
 		assert_eq!(
-			add("add test.example.org. f300 IN AAAA ::1"),
-			Err(NomErr::Error(VerboseError {
-				errors: vec![
-					(
-						"f300 IN AAAA ::1",
-						VerboseErrorKind::Nom(ErrorKind::TakeWhile1),
-					),
-					("f300 IN AAAA ::1", VerboseErrorKind::Context("ttl")),
-					(
-						"add test.example.org. f300 IN AAAA ::1",
-						VerboseErrorKind::Context("add"),
-					),
-				]
+			context("add x.example.org. 300 IN A banana"),
+			Some("IPv4 address")
+		);
+		assert_eq!(
+			context("add x.example.org. 300 IN AAAA 1.2.3.4"),
+			Some("IPv6 address")
+		);
+		assert_eq!(
+			context("add x.example.org. 300 IN MX 70000 mail"),
+			Some("preference")
+		);
+		// Unknown types are not a hard failure because the line could still be another command
+		assert!(matches!(
+			add("add x.example.org. 300 IN BANANA 1.2.3.4"),
+			Err(NomErr::Error(e)) if e.errors.iter().any(|(_, kind)| *kind == VerboseErrorKind::Context("record type"))
+		));
+		assert_eq!(
+			parse("delete x.example.org. ANY"),
+			Ok(Command::Update(Update::Delete(Delete {
+				name: "x.example.org.".to_string(),
+				class: None,
+				type_: None,
+				data: None,
+			})))
+		);
+		assert_eq!(
+			stored_rdata("TXT", r#""a" "b""#),
+			Some(RData::Txt(vec![b"a".to_vec(), b"b".to_vec()]))
+		);
+		assert_eq!(stored_rdata("A", "banana"), None);
+		// Addresses are compared by value and written in their canonical form (RFC 5952)
+		assert_eq!(
+			stored_rdata("AAAA", "2001:DB8:0:0::1"),
+			stored_rdata("AAAA", "2001:0db8::0001")
+		);
+		assert_eq!(
+			stored_rdata("AAAA", "2001:DB8:0:0::1").map(|rdata| rdata.to_string()),
+			Some("2001:db8::1".to_string())
+		);
+	}
+
+	#[test]
+	fn structured_rdata_test() {
+		let data =
+			|type_: &str, data: &str| stored_rdata(type_, data).map(|rdata| rdata.to_string());
+		assert_eq!(
+			data("SRV", "10 60 5060 SIP.example.org."),
+			Some("10 60 5060 sip.example.org.".to_string())
+		);
+		assert_eq!(data("SRV", "10 60 70000 sip.example.org."), None);
+		assert_eq!(
+			data("CAA", "0 ISSUE letsencrypt.org"),
+			Some(r#"0 issue "letsencrypt.org""#.to_string())
+		);
+		assert_eq!(
+			data("CAA", r#"128 iodef "mailto:security@example.org""#),
+			Some(r#"128 iodef "mailto:security@example.org""#.to_string())
+		);
+		assert_eq!(data("CAA", "0 issue-wild letsencrypt.org"), None);
+		assert_eq!(data("CAA", "256 issue letsencrypt.org"), None);
+		let sha256 = "ab".repeat(32);
+		assert_eq!(
+			data("SSHFP", &format!("4 2 {} {}", &sha256[..32], &sha256[32..])),
+			Some(format!("4 2 {}", sha256.to_uppercase()))
+		);
+		assert_eq!(data("SSHFP", &format!("4 1 {sha256}")), None);
+		assert_eq!(data("SSHFP", &format!("5 2 {sha256}")), None);
+		assert_eq!(data("SSHFP", "4 2 abc"), None);
+		assert_eq!(
+			data("TLSA", &format!("3 1 1 {sha256}")),
+			Some(format!("3 1 1 {}", sha256.to_uppercase()))
+		);
+		assert_eq!(data("TLSA", &format!("4 1 1 {sha256}")), None);
+		assert_eq!(data("TLSA", &format!("3 1 2 {sha256}")), None);
+		assert_eq!(
+			data(
+				"HTTPS",
+				r#"1 . port=8443 alpn="h2,h3" ipv6hint=2001:DB8::1 no-default-alpn"#
+			),
+			Some("1 . alpn=h2,h3 no-default-alpn port=8443 ipv6hint=2001:db8::1".to_string())
+		);
+		assert_eq!(
+			data("SVCB", "0 svc.example.org."),
+			Some("0 svc.example.org.".to_string())
+		);
+		assert_eq!(
+			data("SVCB", "1 svc.example.org. key65000 ech=AEX+/w=="),
+			Some("1 svc.example.org. ech=AEX+/w== key65000".to_string())
+		);
+		assert_eq!(data("SVCB", "0 svc.example.org. port=53"), None);
+		assert_eq!(data("SVCB", "1 . port=53 port=54"), None);
+		assert_eq!(data("SVCB", "1 . port=http"), None);
+		assert_eq!(data("SVCB", "1 . no-default-alpn"), None);
+		assert_eq!(data("SVCB", "1 . mandatory=port"), None);
+		assert_eq!(
+			data("SVCB", "1 . mandatory=port port=53"),
+			Some("1 . mandatory=port port=53".to_string())
+		);
+		assert_eq!(data("SVCB", "1 . ech=abc"), None);
+		assert_eq!(
+			data("SVCB", r#"1 . alpn="h2,http/1.1""#),
+			Some("1 . alpn=h2,http/1.1".to_string())
+		);
+		assert_eq!(data("SVCB", r#"1 . alpn="h2\010evil""#), None);
+		assert_eq!(data("SVCB", r#"1 . alpn="h2 h3""#), None);
+		assert_eq!(data("SVCB", r#"1 . alpn="h2;""#), None);
+		assert_eq!(data("SVCB", "1 . banana=1"), None);
+	}
+
+	#[test]
+	fn round_trip_test() {
+		let binding = ServiceBinding {
+			priority: 1,
+			target: "svc.example.org.".to_string(),
+			params: vec![
+				SvcParam::Mandatory(vec![1, 3]),
+				SvcParam::Alpn(vec!["h2".to_string(), "http/1.1".to_string()]),
+				SvcParam::NoDefaultAlpn,
+				SvcParam::Port(8443),
+				SvcParam::Ipv4Hint(vec![
+					Ipv4Addr::new(192, 0, 2, 1),
+					Ipv4Addr::new(192, 0, 2, 2),
+				]),
+				SvcParam::Ech("AEX+/w==".to_string()),
+				SvcParam::Ipv6Hint(vec![Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)]),
+				SvcParam::Unknown {
+					key: 65000,
+					value: b"a \"b\" \\ ; \x00\xff".to_vec(),
+				},
+				SvcParam::Unknown {
+					key: 65001,
+					value: Vec::new(),
+				},
+			],
+		};
+		let tricky = b"quote \" backslash \\ semicolon ; tab \t byte \xff".to_vec();
+		let all = [
+			RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+			RData::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+			RData::Caa {
+				flags: 128,
+				tag: "iodef".to_string(),
+				value: tricky.clone(),
+			},
+			RData::Cname("www.example.org.".to_string()),
+			RData::Dname("example.net.".to_string()),
+			RData::Https(ServiceBinding {
+				priority: 0,
+				target: "alias.example.org.".to_string(),
+				params: Vec::new(),
+			}),
+			RData::Mx {
+				preference: 10,
+				exchange: "mail".to_string(),
+			},
+			RData::Ns("ns1.example.org.".to_string()),
+			RData::Ptr("host.example.org.".to_string()),
+			RData::Spf(vec![b"v=spf1 -all".to_vec()]),
+			RData::Srv {
+				priority: 10,
+				weight: 60,
+				port: 5060,
+				target: "_sip._tcp.example.org.".to_string(),
+			},
+			RData::Sshfp {
+				algorithm: 4,
+				fingerprint_type: 2,
+				fingerprint: vec![0xab; 32],
+			},
+			RData::Svcb(binding),
+			RData::Tlsa {
+				usage: 3,
+				selector: 1,
+				matching_type: 1,
+				data: vec![0xab; 32],
+			},
+			RData::Txt(vec![tricky, Vec::new(), vec![b'x'; 255]]),
+			RData::Unknown {
+				type_: 65534,
+				data: vec![0x0a, 0, 0, 1],
+			},
+			RData::Unknown {
+				type_: 65534,
+				data: Vec::new(),
+			},
+		];
+		for rdata in all {
+			let type_ = rdata.record_type().to_string();
+			assert_eq!(stored_rdata(&type_, &rdata.to_string()), Some(rdata));
+		}
+	}
+
+	#[test]
+	fn generic_test() {
+		assert_eq!(
+			parse(r"update add x.example.org. 300 CLASS1 TYPE65534 \# 4 0A000001"),
+			Ok(Command::Update(Update::Add(Add {
+				name: "x.example.org.".to_string(),
+				ttl: Some(300),
+				class: Some("IN".to_string()),
+				data: RData::Unknown {
+					type_: 65534,
+					data: vec![10, 0, 0, 1],
+				},
+			})))
+		);
+		assert_eq!(
+			stored_rdata("TYPE65534", r"\# 4 0a00 0001").map(|rdata| rdata.to_string()),
+			Some(r"\# 4 0A000001".to_string())
+		);
+		assert_eq!(
+			stored_rdata("TYPE65534", r"\# 0"),
+			Some(RData::Unknown {
+				type_: 65534,
+				data: Vec::new(),
+			})
+		);
+		assert_eq!(stored_rdata("TYPE65534", r"\# 3 0A000001"), None);
+		assert_eq!(stored_rdata("TYPE65534", "10.0.0.1"), None);
+		assert_eq!(stored_rdata("A", r"\# 3 0A0000"), None);
+		assert_eq!(
+			stored_rdata("TYPE1", "10.0.0.1"),
+			Some(RData::A(Ipv4Addr::new(10, 0, 0, 1)))
+		);
+		assert_eq!(
+			parse(r"update delete x.example.org. CLASS32 TYPE65534"),
+			Ok(Command::Update(Update::Delete(Delete {
+				name: "x.example.org.".to_string(),
+				class: Some("CLASS32".to_string()),
+				type_: Some(RecordType::Unknown(65534)),
+				data: None,
+			})))
+		);
+		assert!(parse(r"update delete x.example.org. CLASS255 TYPE65534").is_err());
+	}
+
+	#[test]
+	fn known_generic_test() {
+		let data =
+			|type_: &str, data: &str| stored_rdata(type_, data).map(|rdata| rdata.to_string());
+		assert_eq!(
+			parse(r"update add x.example.org. 300 IN A \# 4 0A000001"),
+			parse("update add x.example.org. 300 IN A 10.0.0.1")
+		);
+		assert_eq!(
+			parse(r"update add x.example.org. 300 IN TYPE1 \# 4 0A000001"),
+			parse("update add x.example.org. 300 IN A 10.0.0.1")
+		);
+		assert_eq!(
+			data("AAAA", r"\# 16 20010DB8000000000000000000000001"),
+			Some("2001:db8::1".to_string())
+		);
+		assert_eq!(
+			data("MX", r"\# 20 000A 046D61696C 076578616D706C65 036F726700"),
+			Some("10 mail.example.org.".to_string())
+		);
+		assert_eq!(
+			data("TXT", r"\# 6 026869 01 2200"),
+			Some(r#""hi" "\"" """#.to_string())
+		);
+		assert_eq!(
+			data("CAA", r"\# 9 00 05 6973737565 6361"),
+			Some(r#"0 issue "ca""#.to_string())
+		);
+		assert_eq!(
+			data(
+				"HTTPS",
+				r"\# 25 0001 00 0001 0006 02683202 6833 0003 0002 20FB 0005 0002 0102"
+			),
+			Some("1 . alpn=h2,h3 port=8443 ech=AQI=".to_string())
+		);
+		assert_eq!(
+			data("SRV", r"\# 9 000A 003C 13C4 017800"),
+			Some("10 60 5060 x.".to_string())
+		);
+		// The data is checked just like in the presentation format
+		assert_eq!(data("A", r"\# 3 0A0000"), None);
+		assert_eq!(data("CNAME", r"\# 4 02612E00"), None);
+		assert_eq!(data("TXT", r"\# 0"), None);
+		assert_eq!(data("SSHFP", r"\# 4 0402ABCD"), None);
+		assert_eq!(data("SVCB", r"\# 7 0000 00 0003 0000"), None);
+	}
+
+	#[test]
+	fn txt_test() {
+		let txt = |data: &str| match parse(&format!("add x.example.org. 300 IN TXT {data}")) {
+			Ok(Command::Update(Update::Add(Add { data, .. }))) => Ok(data.to_string()),
+			Ok(command) => panic!("unexpected command {command:?}"),
+			Err(_) => Err(()),
+		};
+		assert_eq!(txt("foo"), Ok(r#""foo""#.to_string()));
+		assert_eq!(
+			txt(r#""v=spf1 include:foo -all""#),
+			Ok(r#""v=spf1 include:foo -all""#.to_string())
+		);
+		assert_eq!(
+			txt(r#""a b"  c "d\"e\\f""#),
+			Ok(r#""a b" "c" "d\"e\\f""#.to_string())
+		);
+		assert_eq!(txt(r#""\065\b\010""#), Ok(r#""Ab\010""#.to_string()));
+		assert_eq!(txt(r#""""#), Ok(r#""""#.to_string()));
+		assert_eq!(
+			txt(&format!("\"{}\"", "a".repeat(300))),
+			Ok(format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45)))
+		);
+		assert_eq!(txt(r#""unterminated"#), Err(()));
+		assert_eq!(txt(r#"un"quoted"#), Err(()));
+		assert_eq!(txt(r#""\256""#), Err(()));
+	}
+
+	#[test]
+	fn ttl_test() {
+		assert_eq!(ttl("300 IN"), Ok((" IN", 300)));
+		assert_eq!(ttl("1h30m"), Ok(("", 5400)));
+		assert_eq!(ttl("1W"), Ok(("", 604_800)));
+		assert_eq!(ttl("1d2h3m4s"), Ok(("", 93784)));
+		assert_eq!(ttl("1h30"), Ok(("", 3630)));
+		assert_eq!(ttl("2147483647"), Ok(("", 2_147_483_647)));
+		assert!(matches!(ttl("2147483648"), Err(NomErr::Failure(_))));
+		assert!(matches!(
+			ttl("99999999999999999999"),
+			Err(NomErr::Failure(_))
+		));
+		assert!(parse("add test.example.org. 4294967295 IN A 192.0.2.1").is_err());
+		assert_eq!(command("ttl 1h"), Ok(("", Command::Ttl(Some(3600)))));
+	}
+
+	#[test]
+	fn wildcard_test() {
+		assert_eq!(
+			owner("*.Preview.example.org. 300"),
+			Ok((" 300", "*.preview.example.org.".to_string()))
+		);
+		assert_eq!(owner("* 300"), Ok((" 300", "*".to_string())));
+		assert_eq!(
+			add("add *.preview.example.org. 300 IN A 192.0.2.1"),
+			Ok((
+				"",
+				Command::Update(Update::Add(Add {
+					name: "*.preview.example.org.".to_string(),
+					ttl: Some(300),
+					class: Some("IN".to_string()),
+					data: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+				}))
+			))
+		);
+		assert!(parse("add foo.*.example.org. 300 IN A 192.0.2.1").is_err());
+	}
+
+	#[test]
+	fn name_test() {
+		let context = |line: &str| match parse(line) {
+			Err(NomErr::Error(e) | NomErr::Failure(e)) => {
+				e.errors.iter().find_map(|(_, kind)| match kind {
+					VerboseErrorKind::Context(context) => Some(*context),
+					_ => None,
+				})
+			}
+			_ => None,
+		};
+		let long_label = "a".repeat(64);
+		let long_name = format!("{}example.org.", "abcdefghi.".repeat(25));
+		assert_eq!(
+			context("add x.example.org. 300 IN CNAME a..b."),
+			Some("name without empty labels")
+		);
+		assert_eq!(
+			context("add x.example.org. 300 IN MX 10 -."),
+			Some("labels that do not start or end with a hyphen")
+		);
+		assert_eq!(
+			context(&format!(
+				"add x.example.org. 300 IN NS {long_label}.example.org."
+			)),
+			Some("labels of at most 63 characters")
+		);
+		assert_eq!(
+			context(&format!("add x.example.org. 300 IN PTR {long_name}")),
+			Some("name of at most 253 characters")
+		);
+		assert!(parse("add a..example.org. 300 IN A 192.0.2.1").is_err());
+		assert!(parse(&format!("add {long_label}.example.org. 300 IN A 192.0.2.1")).is_err());
+		assert!(parse("add .example.org. 300 IN A 192.0.2.1").is_err());
+		assert!(
+			parse(&format!(
+				"add {}.example.org. 300 IN A 192.0.2.1",
+				"a".repeat(63)
+			))
+			.is_ok()
+		);
+		assert!(parse("add x.example.org. 300 IN SVCB 1 .").is_ok());
+	}
+
+	#[test]
+	fn idn_test() {
+		assert_eq!(
+			parse_name("Bücher.de"),
+			Some("xn--bcher-kva.de".to_string())
+		);
+		assert_eq!(parse_name("a..b"), None);
+		assert_eq!(parse_name("example.org 300"), None);
+		assert_eq!(
+			owner("Bücher.example.org. 300"),
+			Ok((" 300", "xn--bcher-kva.example.org.".to_string()))
+		);
+		assert_eq!(
+			owner("*.bücher.example.org. 300"),
+			Ok((" 300", "*.xn--bcher-kva.example.org.".to_string()))
+		);
+		assert_eq!(
+			add("add www.example.org. 300 IN CNAME bücher.example.org."),
+			Ok((
+				"",
+				Command::Update(Update::Add(Add {
+					name: "www.example.org.".to_string(),
+					ttl: Some(300),
+					class: Some("IN".to_string()),
+					data: RData::Cname("xn--bcher-kva.example.org.".to_string()),
+				}))
+			))
+		);
+		assert_eq!(
+			add("add example.org. 300 IN MX 10  mäil.example.org."),
+			Ok((
+				"",
+				Command::Update(Update::Add(Add {
+					name: "example.org.".to_string(),
+					ttl: Some(300),
+					class: Some("IN".to_string()),
+					data: RData::Mx {
+						preference: 10,
+						exchange: "xn--mil-qla.example.org.".to_string(),
+					},
+				}))
+			))
+		);
+	}
+
+	#[test]
+	fn prereq_test() {
+		assert_eq!(
+			command("prereq nxdomain host.example.org."),
+			Ok((
+				"",
+				Command::Prereq(Prereq {
+					condition: Condition::Nxdomain,
+					name: "host.example.org.".to_string(),
+					class: None,
+					type_: None,
+					data: None,
+				})
+			))
+		);
+		assert_eq!(
+			command("prereq nxrrset host.example.org. IN aaaa"),
+			Ok((
+				"",
+				Command::Prereq(Prereq {
+					condition: Condition::Nxrrset,
+					name: "host.example.org.".to_string(),
+					class: Some("IN".to_string()),
+					type_: Some(RecordType::Aaaa),
+					data: None,
+				})
+			))
+		);
+		assert_eq!(
+			command("prereq yxrrset host.example.org. TXT \"a b\""),
+			Ok((
+				"",
+				Command::Prereq(Prereq {
+					condition: Condition::Yxrrset,
+					name: "host.example.org.".to_string(),
+					class: None,
+					type_: Some(RecordType::Txt),
+					data: Some(RData::Txt(vec![b"a b".to_vec()])),
+				})
+			))
+		);
+		assert!(parse("prereq nxrrset host.example.org.").is_err());
+	}
+
+	#[test]
+	fn whitespace_test() {
+		assert_eq!(
+			parse("update  add\texample.org. \t300   IN\tA  1.2.3.4"),
+			Ok(Command::Update(Update::Add(Add {
+				name: "example.org.".to_string(),
+				ttl: Some(300),
+				class: Some("IN".to_string()),
+				data: RData::A(Ipv4Addr::new(1, 2, 3, 4)),
+			})))
+		);
+		assert_eq!(
+			parse("del\texample.org.  A\t1.2.3.4"),
+			Ok(Command::Update(Update::Delete(Delete {
+				name: "example.org.".to_string(),
+				class: None,
+				type_: Some(RecordType::A),
+				data: Some(RData::A(Ipv4Addr::new(1, 2, 3, 4))),
+			})))
+		);
+		assert_eq!(
+			parse("prereq\tyxrrset  example.org.\tIN  A"),
+			Ok(Command::Prereq(Prereq {
+				condition: Condition::Yxrrset,
+				name: "example.org.".to_string(),
+				class: Some("IN".to_string()),
+				type_: Some(RecordType::A),
+				data: None,
+			}))
+		);
+		assert_eq!(
+			parse("prereq  nxdomain\texample.org."),
+			Ok(Command::Prereq(Prereq {
+				condition: Condition::Nxdomain,
+				name: "example.org.".to_string(),
+				class: None,
+				type_: None,
+				data: None,
 			}))
 		);
+		assert_eq!(
+			parse("zone\t example.org."),
+			Ok(Command::Zone(Some("example.org.".to_string())))
+		);
+		assert_eq!(parse("ttl  60"), Ok(Command::Ttl(Some(60))));
+		assert_eq!(parse("class\tCH"), Ok(Command::Class("CH".to_string())));
 	}
 
 	#[test]
@@ -252,9 +1907,10 @@ mod test {
 			Ok((
 				"",
 				Command::Update(Update::Delete(Delete {
-					name: "test.example.org.",
-					class: "IN",
-					type_: "A",
+					name: "test.example.org.".to_string(),
+					class: Some("IN".to_string()),
+					type_: Some(RecordType::A),
+					data: None,
 				}))
 			))
 		);
@@ -263,9 +1919,46 @@ mod test {
 			Ok((
 				"",
 				Command::Update(Update::Delete(Delete {
-					name: "test.example.org.",
-					class: "IN",
-					type_: "AAAA",
+					name: "test.example.org.".to_string(),
+					class: Some("IN".to_string()),
+					type_: Some(RecordType::Aaaa),
+					data: None,
+				}))
+			))
+		);
+		assert_eq!(
+			delete("delete test.example.org."),
+			Ok((
+				"",
+				Command::Update(Update::Delete(Delete {
+					name: "test.example.org.".to_string(),
+					class: None,
+					type_: None,
+					data: None,
+				}))
+			))
+		);
+		assert_eq!(
+			delete("delete test.example.org. AAAA"),
+			Ok((
+				"",
+				Command::Update(Update::Delete(Delete {
+					name: "test.example.org.".to_string(),
+					class: None,
+					type_: Some(RecordType::Aaaa),
+					data: None,
+				}))
+			))
+		);
+		assert_eq!(
+			delete("delete test.example.org. 300 IN AAAA ::1"),
+			Ok((
+				"",
+				Command::Update(Update::Delete(Delete {
+					name: "test.example.org.".to_string(),
+					class: Some("IN".to_string()),
+					type_: Some(RecordType::Aaaa),
+					data: Some(RData::Aaaa(Ipv6Addr::LOCALHOST)),
 				}))
 			))
 		);
@@ -295,27 +1988,74 @@ mod test {
 		assert_eq!(
 			parse("update delete example.org. IN A"),
 			Ok(Command::Update(Update::Delete(Delete {
-				name: "example.org.",
-				class: "IN",
-				type_: "A",
+				name: "example.org.".to_string(),
+				class: Some("IN".to_string()),
+				type_: Some(RecordType::A),
+				data: None,
 			})))
 		);
 		assert_eq!(
 			parse("delete example.org. IN A"),
 			Ok(Command::Update(Update::Delete(Delete {
-				name: "example.org.",
-				class: "IN",
-				type_: "A",
+				name: "example.org.".to_string(),
+				class: Some("IN".to_string()),
+				type_: Some(RecordType::A),
+				data: None,
 			})))
 		);
 		assert_eq!(
 			parse("add example.org. 123 IN A 1.2.3.4"),
 			Ok(Command::Update(Update::Add(Add {
-				name: "example.org.",
-				ttl: 123,
-				class: "IN",
-				type_: "A",
-				data: "1.2.3.4",
+				name: "example.org.".to_string(),
+				ttl: Some(123),
+				class: Some("IN".to_string()),
+				data: RData::A(Ipv4Addr::new(1, 2, 3, 4)),
+			})))
+		);
+		assert_eq!(
+			parse(r#"update add _acme-challenge.www.example.org. 60 IN TXT "token""#),
+			Ok(Command::Update(Update::Add(Add {
+				name: "_acme-challenge.www.example.org.".to_string(),
+				ttl: Some(60),
+				class: Some("IN".to_string()),
+				data: RData::Txt(vec![b"token".to_vec()]),
+			})))
+		);
+		assert_eq!(
+			parse(r#"update delete _acme-challenge.www.example.org. TXT "token""#),
+			Ok(Command::Update(Update::Delete(Delete {
+				name: "_acme-challenge.www.example.org.".to_string(),
+				class: None,
+				type_: Some(RecordType::Txt),
+				data: Some(RData::Txt(vec![b"token".to_vec()])),
+			})))
+		);
+		assert_eq!(
+			parse("update delete _sip._tcp.example.org. SRV"),
+			Ok(Command::Update(Update::Delete(Delete {
+				name: "_sip._tcp.example.org.".to_string(),
+				class: None,
+				type_: Some(RecordType::Srv),
+				data: None,
+			})))
+		);
+		assert_eq!(parse("HELP"), Ok(Command::Help));
+		assert_eq!(
+			parse("Update ADD WWW.Example.org. 300 in a 1.2.3.4"),
+			Ok(Command::Update(Update::Add(Add {
+				name: "www.example.org.".to_string(),
+				ttl: Some(300),
+				class: Some("IN".to_string()),
+				data: RData::A(Ipv4Addr::new(1, 2, 3, 4)),
+			})))
+		);
+		assert_eq!(
+			parse("update Del WWW.Example.org. Txt"),
+			Ok(Command::Update(Update::Delete(Delete {
+				name: "www.example.org.".to_string(),
+				class: None,
+				type_: Some(RecordType::Txt),
+				data: None,
 			})))
 		);
 		assert_eq!(
@@ -0,0 +1,116 @@
+// SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use color_eyre::eyre::{Result, WrapErr};
+use std::{collections::HashSet, fs, path::Path};
+
+// Snapshot of https://publicsuffix.org/list/public_suffix_list.dat so that no network access is ever needed
+const EMBEDDED_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+pub struct PublicSuffixList {
+	rules: HashSet<String>,
+	wildcard_rules: HashSet<String>,
+	exception_rules: HashSet<String>,
+}
+
+impl PublicSuffixList {
+	pub fn embedded() -> Self {
+		Self::parse(EMBEDDED_LIST)
+	}
+
+	pub fn load(path: &Path) -> Result<Self> {
+		let list = fs::read_to_string(path).wrap_err_with(|| {
+			format!("Cannot read public suffix list file `{}`", path.display())
+		})?;
+		Ok(Self::parse(&list))
+	}
+
+	fn parse(list: &str) -> Self {
+		let mut psl = Self {
+			rules: HashSet::new(),
+			wildcard_rules: HashSet::new(),
+			exception_rules: HashSet::new(),
+		};
+		for line in list.lines() {
+			// Only use the ICANN section, suffixes from the private section can still be declared as zones
+			if line.contains("===END ICANN DOMAINS===") {
+				break;
+			}
+			// Everything after the first whitespace is ignored
+			let rule = line.split_whitespace().next().unwrap_or_default();
+			if rule.is_empty() || rule.starts_with("//") {
+				continue;
+			}
+			// Names are converted to A-labels before they are looked up, so the rules have to be as well
+			let to_ascii = |rule: &str| idna::domain_to_ascii(rule).ok();
+			if let Some(rule) = rule.strip_prefix("*.") {
+				psl.wildcard_rules.extend(to_ascii(rule));
+			} else if let Some(rule) = rule.strip_prefix('!') {
+				psl.exception_rules.extend(to_ascii(rule));
+			} else {
+				psl.rules.extend(to_ascii(rule));
+			}
+		}
+		psl
+	}
+
+	/// Number of labels at the end of the name that form its public suffix, `None` if no rule matches
+	pub fn suffix_len(&self, labels: &[&str]) -> Option<usize> {
+		// Try the longest candidate first, the first matching rule is the prevailing one
+		for start in 0..labels.len() {
+			let candidate = labels[start..].join(".");
+			if self.exception_rules.contains(&candidate) {
+				return Some(labels.len() - start - 1);
+			}
+			if self.rules.contains(&candidate) {
+				return Some(labels.len() - start);
+			}
+			if start + 1 < labels.len()
+				&& self.wildcard_rules.contains(&labels[start + 1..].join("."))
+			{
+				return Some(labels.len() - start);
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::PublicSuffixList;
+	use indoc::indoc;
+
+	#[test]
+	fn suffix_len_test() {
+		let psl = PublicSuffixList::parse(indoc! {"
+			// ===BEGIN ICANN DOMAINS===
+			org
+			uk
+			co.uk
+			ck
+			*.ck
+			!www.ck
+			рф
+			// ===END ICANN DOMAINS===
+			// ===BEGIN PRIVATE DOMAINS===
+			github.io
+		"});
+		assert_eq!(psl.suffix_len(&["test", "example", "org"]), Some(1));
+		assert_eq!(psl.suffix_len(&["example", "co", "uk"]), Some(2));
+		assert_eq!(psl.suffix_len(&["co", "uk"]), Some(2));
+		assert_eq!(psl.suffix_len(&["test", "example", "ck"]), Some(2));
+		assert_eq!(psl.suffix_len(&["test", "www", "ck"]), Some(1));
+		assert_eq!(psl.suffix_len(&["xn--e1afmkfd", "xn--p1ai"]), Some(1));
+		assert_eq!(psl.suffix_len(&["example", "github", "io"]), None);
+		assert_eq!(psl.suffix_len(&["example", "invalid"]), None);
+	}
+
+	#[test]
+	fn embedded_test() {
+		let psl = PublicSuffixList::embedded();
+		assert_eq!(psl.suffix_len(&["test", "example", "org"]), Some(1));
+		assert_eq!(psl.suffix_len(&["test", "example", "co", "uk"]), Some(2));
+		// пример.рф
+		assert_eq!(psl.suffix_len(&["xn--e1afmkfd", "xn--p1ai"]), Some(1));
+	}
+}
@@ -0,0 +1,531 @@
+// SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::zone_files;
+use std::{
+	convert::Infallible,
+	fmt,
+	net::{Ipv4Addr, Ipv6Addr},
+	str::FromStr,
+};
+
+/// Record types that zonegen knows how to parse and write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+	A,
+	Aaaa,
+	Caa,
+	Cname,
+	Dname,
+	Https,
+	Mx,
+	Ns,
+	Ptr,
+	Spf,
+	Srv,
+	Sshfp,
+	Svcb,
+	Tlsa,
+	Txt,
+	/// Any other type, written as `TYPEnnn` (RFC 3597 section 5)
+	Unknown(u16),
+}
+
+impl RecordType {
+	const ALL: [Self; 15] = [
+		Self::A,
+		Self::Aaaa,
+		Self::Caa,
+		Self::Cname,
+		Self::Dname,
+		Self::Https,
+		Self::Mx,
+		Self::Ns,
+		Self::Ptr,
+		Self::Spf,
+		Self::Srv,
+		Self::Sshfp,
+		Self::Svcb,
+		Self::Tlsa,
+		Self::Txt,
+	];
+
+	const fn mnemonic(self) -> Option<&'static str> {
+		Some(match self {
+			Self::A => "A",
+			Self::Aaaa => "AAAA",
+			Self::Caa => "CAA",
+			Self::Cname => "CNAME",
+			Self::Dname => "DNAME",
+			Self::Https => "HTTPS",
+			Self::Mx => "MX",
+			Self::Ns => "NS",
+			Self::Ptr => "PTR",
+			Self::Spf => "SPF",
+			Self::Srv => "SRV",
+			Self::Sshfp => "SSHFP",
+			Self::Svcb => "SVCB",
+			Self::Tlsa => "TLSA",
+			Self::Txt => "TXT",
+			Self::Unknown(_) => return None,
+		})
+	}
+
+	pub const fn code(self) -> u16 {
+		match self {
+			Self::A => 1,
+			Self::Aaaa => 28,
+			Self::Caa => 257,
+			Self::Cname => 5,
+			Self::Dname => 39,
+			Self::Https => 65,
+			Self::Mx => 15,
+			Self::Ns => 2,
+			Self::Ptr => 12,
+			Self::Spf => 99,
+			Self::Srv => 33,
+			Self::Sshfp => 44,
+			Self::Svcb => 64,
+			Self::Tlsa => 52,
+			Self::Txt => 16,
+			Self::Unknown(code) => code,
+		}
+	}
+
+	fn from_code(code: u16) -> Option<Self> {
+		// 0 is reserved, OPT and the meta types or QTYPEs like ANY cannot be stored in a zone (RFC 6895 section 3.1)
+		if matches!(code, 0 | 41 | 128..=255) {
+			return None;
+		}
+		Some(
+			Self::ALL
+				.into_iter()
+				.find(|type_| type_.code() == code)
+				.unwrap_or(Self::Unknown(code)),
+		)
+	}
+}
+
+impl FromStr for RecordType {
+	type Err = UnknownRecordType;
+
+	fn from_str(mnemonic: &str) -> Result<Self, Self::Err> {
+		// Types are case-insensitive
+		if let Some(type_) = Self::ALL.into_iter().find(|type_| {
+			type_
+				.mnemonic()
+				.is_some_and(|known| known.eq_ignore_ascii_case(mnemonic))
+		}) {
+			return Ok(type_);
+		}
+		mnemonic
+			.get(..4)
+			.filter(|prefix| prefix.eq_ignore_ascii_case("TYPE"))
+			.and_then(|_| parse_code(&mnemonic[4..]))
+			.and_then(Self::from_code)
+			.ok_or(UnknownRecordType)
+	}
+}
+
+impl fmt::Display for RecordType {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		// Padding is supported so that the types line up in the zone files
+		match self.mnemonic() {
+			Some(mnemonic) => f.pad(mnemonic),
+			None => f.pad(&format!("TYPE{}", self.code())),
+		}
+	}
+}
+
+/// Number of a `TYPEnnn` or `CLASSnnn` mnemonic (RFC 3597 section 5)
+pub fn parse_code(digits: &str) -> Option<u16> {
+	if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	digits.parse().ok()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownRecordType;
+
+/// Data of a record, with the fields of each type in a structured form.
+/// Domain names are in their ASCII form and are relative to the origin of the zone file if they don't end in a dot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+	A(Ipv4Addr),
+	Aaaa(Ipv6Addr),
+	/// RFC 8659
+	Caa {
+		flags: u8,
+		/// Lowercase, like `issue` or `iodef`
+		tag: String,
+		value: Vec<u8>,
+	},
+	Cname(String),
+	Dname(String),
+	Https(ServiceBinding),
+	Mx {
+		preference: u16,
+		exchange: String,
+	},
+	Ns(String),
+	Ptr(String),
+	/// Character strings of at most 255 bytes each
+	Spf(Vec<Vec<u8>>),
+	/// RFC 2782
+	Srv {
+		priority: u16,
+		weight: u16,
+		port: u16,
+		target: String,
+	},
+	/// RFC 4255
+	Sshfp {
+		algorithm: u8,
+		fingerprint_type: u8,
+		fingerprint: Vec<u8>,
+	},
+	Svcb(ServiceBinding),
+	/// RFC 6698
+	Tlsa {
+		usage: u8,
+		selector: u8,
+		matching_type: u8,
+		data: Vec<u8>,
+	},
+	/// Character strings of at most 255 bytes each
+	Txt(Vec<Vec<u8>>),
+	/// Data of an unknown type in the generic format `\# <length> <hex>` (RFC 3597 section 5)
+	Unknown {
+		type_: u16,
+		data: Vec<u8>,
+	},
+}
+
+/// Data of SVCB and HTTPS records (RFC 9460)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceBinding {
+	/// 0 is the alias mode, which has no parameters
+	pub priority: u16,
+	pub target: String,
+	/// Sorted by key without duplicates, as they have to be on the wire
+	pub params: Vec<SvcParam>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvcParam {
+	Mandatory(Vec<u16>),
+	Alpn(Vec<String>),
+	NoDefaultAlpn,
+	Port(u16),
+	Ipv4Hint(Vec<Ipv4Addr>),
+	/// Base64 encoded ECHConfigList
+	Ech(String),
+	Ipv6Hint(Vec<Ipv6Addr>),
+	/// A key without a name, written as `keyNNNNN`
+	Unknown {
+		key: u16,
+		value: Vec<u8>,
+	},
+}
+
+impl SvcParam {
+	const NAMES: [&'static str; 7] = [
+		"mandatory",
+		"alpn",
+		"no-default-alpn",
+		"port",
+		"ipv4hint",
+		"ech",
+		"ipv6hint",
+	];
+
+	pub const fn key(&self) -> u16 {
+		match self {
+			Self::Mandatory(_) => 0,
+			Self::Alpn(_) => 1,
+			Self::NoDefaultAlpn => 2,
+			Self::Port(_) => 3,
+			Self::Ipv4Hint(_) => 4,
+			Self::Ech(_) => 5,
+			Self::Ipv6Hint(_) => 6,
+			Self::Unknown { key, .. } => *key,
+		}
+	}
+
+	/// Number of a key given by its name or as `keyNNNNN`, `None` for the reserved key 65535
+	pub fn parse_key(name: &str) -> Option<u16> {
+		let name = name.to_ascii_lowercase();
+		if let Some(key) = Self::NAMES.iter().position(|known| *known == name) {
+			return u16::try_from(key).ok();
+		}
+		name.strip_prefix("key")
+			.filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+			.and_then(|digits| digits.parse().ok())
+			.filter(|key| *key != u16::MAX)
+	}
+
+	pub fn key_name(key: u16) -> String {
+		Self::NAMES
+			.get(usize::from(key))
+			.map_or_else(|| format!("key{key}"), ToString::to_string)
+	}
+}
+
+fn join<T: fmt::Display>(values: &[T]) -> String {
+	values
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join(",")
+}
+
+impl fmt::Display for SvcParam {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&Self::key_name(self.key()))?;
+		match self {
+			Self::Mandatory(keys) => {
+				let names: Vec<String> = keys.iter().map(|key| Self::key_name(*key)).collect();
+				write!(f, "={}", names.join(","))
+			}
+			Self::Alpn(ids) => write!(f, "={}", ids.join(",")),
+			Self::NoDefaultAlpn => Ok(()),
+			Self::Port(port) => write!(f, "={port}"),
+			Self::Ipv4Hint(addresses) => write!(f, "={}", join(addresses)),
+			Self::Ech(config) => write!(f, "={config}"),
+			Self::Ipv6Hint(addresses) => write!(f, "={}", join(addresses)),
+			Self::Unknown { value, .. } if value.is_empty() => Ok(()),
+			Self::Unknown { value, .. } => {
+				write!(f, "={}", zone_files::quote_character_string(value))
+			}
+		}
+	}
+}
+
+impl fmt::Display for ServiceBinding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {}", self.priority, self.target)?;
+		for param in &self.params {
+			write!(f, " {param}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Binary data in the hexadecimal presentation format
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for byte in self.0 {
+			write!(f, "{byte:02X}")?;
+		}
+		Ok(())
+	}
+}
+
+impl RData {
+	pub const fn record_type(&self) -> RecordType {
+		match self {
+			Self::A(_) => RecordType::A,
+			Self::Aaaa(_) => RecordType::Aaaa,
+			Self::Caa { .. } => RecordType::Caa,
+			Self::Cname(_) => RecordType::Cname,
+			Self::Dname(_) => RecordType::Dname,
+			Self::Https(_) => RecordType::Https,
+			Self::Mx { .. } => RecordType::Mx,
+			Self::Ns(_) => RecordType::Ns,
+			Self::Ptr(_) => RecordType::Ptr,
+			Self::Spf(_) => RecordType::Spf,
+			Self::Srv { .. } => RecordType::Srv,
+			Self::Sshfp { .. } => RecordType::Sshfp,
+			Self::Svcb(_) => RecordType::Svcb,
+			Self::Tlsa { .. } => RecordType::Tlsa,
+			Self::Txt(_) => RecordType::Txt,
+			Self::Unknown { type_, .. } => RecordType::Unknown(*type_),
+		}
+	}
+
+	/// Replace every domain name in the data, e.g. to display internationalized names with their U-labels
+	pub fn map_names(self, f: impl Fn(&str) -> String) -> Self {
+		let Ok(data) = self.try_map_names(|name| Ok::<_, Infallible>(f(name)));
+		data
+	}
+
+	/// Like `map_names` but the replacement can fail, e.g. when a relative name cannot be made absolute
+	pub fn try_map_names<E>(self, f: impl Fn(&str) -> Result<String, E>) -> Result<Self, E> {
+		Ok(match self {
+			Self::Cname(name) => Self::Cname(f(&name)?),
+			Self::Dname(name) => Self::Dname(f(&name)?),
+			Self::Mx {
+				preference,
+				exchange,
+			} => Self::Mx {
+				preference,
+				exchange: f(&exchange)?,
+			},
+			Self::Ns(name) => Self::Ns(f(&name)?),
+			Self::Ptr(name) => Self::Ptr(f(&name)?),
+			Self::Srv {
+				priority,
+				weight,
+				port,
+				target,
+			} => Self::Srv {
+				priority,
+				weight,
+				port,
+				target: f(&target)?,
+			},
+			Self::Https(binding) => Self::Https(ServiceBinding {
+				target: f(&binding.target)?,
+				..binding
+			}),
+			Self::Svcb(binding) => Self::Svcb(ServiceBinding {
+				target: f(&binding.target)?,
+				..binding
+			}),
+			data @ (Self::A(_)
+			| Self::Aaaa(_)
+			| Self::Caa { .. }
+			| Self::Spf(_)
+			| Self::Sshfp { .. }
+			| Self::Tlsa { .. }
+			| Self::Txt(_)
+			| Self::Unknown { .. }) => data,
+		})
+	}
+}
+
+impl fmt::Display for RData {
+	/// Presentation format of the data (RFC 1035 section 5.1), which is also how it is stored in the database
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::A(address) => write!(f, "{address}"),
+			Self::Aaaa(address) => write!(f, "{address}"),
+			Self::Cname(name) | Self::Dname(name) | Self::Ns(name) | Self::Ptr(name) => {
+				f.write_str(name)
+			}
+			Self::Mx {
+				preference,
+				exchange,
+			} => write!(f, "{preference} {exchange}"),
+			Self::Caa { flags, tag, value } => write!(
+				f,
+				"{flags} {tag} {}",
+				zone_files::quote_character_string(value)
+			),
+			Self::Https(binding) | Self::Svcb(binding) => write!(f, "{binding}"),
+			Self::Srv {
+				priority,
+				weight,
+				port,
+				target,
+			} => write!(f, "{priority} {weight} {port} {target}"),
+			Self::Sshfp {
+				algorithm,
+				fingerprint_type,
+				fingerprint,
+			} => write!(f, "{algorithm} {fingerprint_type} {}", Hex(fingerprint)),
+			Self::Tlsa {
+				usage,
+				selector,
+				matching_type,
+				data,
+			} => write!(f, "{usage} {selector} {matching_type} {}", Hex(data)),
+			Self::Unknown { data, .. } if data.is_empty() => f.write_str("\\# 0"),
+			Self::Unknown { data, .. } => write!(f, "\\# {} {}", data.len(), Hex(data)),
+			Self::Spf(strings) | Self::Txt(strings) => {
+				let quoted: Vec<String> = strings
+					.iter()
+					.map(|string| zone_files::quote_character_string(string))
+					.collect();
+				f.write_str(&quoted.join(" "))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{RData, RecordType, ServiceBinding, SvcParam};
+	use std::net::Ipv6Addr;
+
+	#[test]
+	fn record_type_test() {
+		assert_eq!("aaaa".parse(), Ok(RecordType::Aaaa));
+		assert_eq!("CNAME".parse(), Ok(RecordType::Cname));
+		assert!("BANANA".parse::<RecordType>().is_err());
+		assert_eq!(RecordType::Txt.to_string(), "TXT");
+		assert_eq!("TYPE65534".parse(), Ok(RecordType::Unknown(65534)));
+		assert_eq!("type1".parse(), Ok(RecordType::A));
+		assert!("TYPE255".parse::<RecordType>().is_err());
+		assert!("TYPE65536".parse::<RecordType>().is_err());
+		assert!("TYPE+1".parse::<RecordType>().is_err());
+		assert_eq!(RecordType::Unknown(65534).to_string(), "TYPE65534");
+	}
+
+	#[test]
+	fn display_test() {
+		assert_eq!(
+			RData::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)).to_string(),
+			"2001:db8::1"
+		);
+		assert_eq!(
+			RData::Mx {
+				preference: 10,
+				exchange: "mail.example.org.".to_string()
+			}
+			.to_string(),
+			"10 mail.example.org."
+		);
+		assert_eq!(
+			RData::Txt(vec![b"a \"b\"".to_vec(), b"c".to_vec()]).to_string(),
+			r#""a \"b\"" "c""#
+		);
+		assert_eq!(
+			RData::Https(ServiceBinding {
+				priority: 1,
+				target: ".".to_string(),
+				params: vec![
+					SvcParam::Alpn(vec!["h2".to_string(), "h3".to_string()]),
+					SvcParam::Port(8443),
+					SvcParam::Unknown {
+						key: 65000,
+						value: b"x y".to_vec()
+					},
+				],
+			})
+			.to_string(),
+			r#"1 . alpn=h2,h3 port=8443 key65000="x y""#
+		);
+		assert_eq!(
+			RData::Sshfp {
+				algorithm: 4,
+				fingerprint_type: 2,
+				fingerprint: vec![0xab; 32],
+			}
+			.to_string(),
+			format!("4 2 {}", "AB".repeat(32))
+		);
+		assert_eq!(
+			RData::Unknown {
+				type_: 65534,
+				data: vec![10, 0, 0, 1]
+			}
+			.to_string(),
+			r"\# 4 0A000001"
+		);
+	}
+
+	#[test]
+	fn svc_param_key_test() {
+		assert_eq!(SvcParam::parse_key("ALPN"), Some(1));
+		assert_eq!(SvcParam::parse_key("key3"), Some(3));
+		assert_eq!(SvcParam::parse_key("key65000"), Some(65000));
+		assert_eq!(SvcParam::parse_key("key65535"), None);
+		assert_eq!(SvcParam::parse_key("banana"), None);
+		assert_eq!(SvcParam::key_name(6), "ipv6hint");
+		assert_eq!(SvcParam::key_name(7), "key7");
+	}
+}
@@ -2,23 +2,437 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use crate::db;
-use crate::parse::{
-	Command,
-	Update::{Add, Delete},
-	parse,
-};
+use crate::parse::{Add, Command, Delete, Prereq, Update, parse};
+use crate::psl::PublicSuffixList;
+use crate::tsig;
 use color_eyre::eyre::{Result, WrapErr};
 use indoc::printdoc;
 use nom_language::error::convert_error;
 use rustyline::{DefaultEditor, error::ReadlineError};
 use sqlx::{Pool, Sqlite, Transaction};
-use tldextract::{TldExtractor, TldOption};
+use std::io::BufRead;
 
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
-pub async fn repl(pool: &Pool<Sqlite>) -> Result<()> {
-	let mut optional_tx: Option<Transaction<Sqlite>> = None;
-	let tld_ext = TldExtractor::new(TldOption::default());
+#[derive(PartialEq, Eq)]
+enum Flow {
+	Continue,
+	Quit,
+}
+
+#[allow(clippy::struct_excessive_bools)] // The settings are independent of each other
+struct Session<'a> {
+	pool: &'a Pool<Sqlite>,
+	optional_tx: Option<Transaction<'a, Sqlite>>,
+	scope: db::Scope<'a>,
+	/// Default TTL of added records, set with the `ttl` command
+	ttl: Option<u32>,
+	/// Default class of added and deleted records, set with the `class` command
+	class: Option<String>,
+	/// Prerequisites of the pending update, checked when it is sent
+	prereqs: Vec<db::PendingPrereq>,
+	/// Records of the update section of the pending update, displayed by the `show` command
+	updates: Vec<String>,
+	/// Status of the last `send`, displayed by the `answer` command
+	answer: Option<String>,
+	/// Keys that may make changes and the key given with -k, -y or the `key` command
+	authorization: tsig::Authorization,
+	/// Print each request, set with the `debug` command or the -d flag
+	debug: bool,
+	/// Whether an empty line sends the pending update
+	blank_line_sends: bool,
+	/// Whether a human is typing the commands, which enables the prompt and the echo of each request
+	interactive: bool,
+	/// Number of the line that is currently being executed, used in error messages
+	line_number: usize,
+	/// Whether any command of this session failed
+	failed: bool,
+}
+
+impl<'a> Session<'a> {
+	const fn new(
+		pool: &'a Pool<Sqlite>,
+		psl: Option<&'a PublicSuffixList>,
+		interactive: bool,
+		debug: bool,
+	) -> Self {
+		Self {
+			pool,
+			optional_tx: None,
+			scope: db::Scope {
+				psl,
+				zone: None,
+				origin: None,
+			},
+			ttl: None,
+			class: None,
+			prereqs: Vec::new(),
+			updates: Vec::new(),
+			answer: None,
+			authorization: tsig::Authorization::unrestricted(),
+			debug,
+			// Scripts rely on this, but a human would be surprised by it
+			blank_line_sends: !interactive,
+			interactive,
+			line_number: 0,
+			failed: false,
+		}
+	}
+
+	async fn execute(&mut self, line: &str) -> Result<Flow> {
+		self.line_number += 1;
+		// Like in nsupdate, commands may be indented, e.g. in a heredoc, and followed by spaces
+		match line.trim_matches([' ', '\t']) {
+			// Like in nsupdate, an empty line sends the pending update
+			blank_line if blank_line.trim().is_empty() => {
+				if self.blank_line_sends {
+					self.send().await?;
+				}
+			}
+			comment if comment.trim_start().starts_with([';', '#']) => {}
+			non_empty_line => match parse(non_empty_line) {
+				Ok(command) => return self.run(command).await,
+				Err(e) => {
+					let message = match e {
+						// TODO: somehow use wrap_err_with() here?
+						nom::Err::Incomplete(f) => format!("Incomplete: {f:?}"),
+						// Print a hopefully nicer error message
+						nom::Err::Error(f) | nom::Err::Failure(f) => {
+							format!("Syntax error\n{}", convert_error(non_empty_line, f))
+						}
+					};
+					self.report(&message);
+				}
+			},
+		}
+		Ok(Flow::Continue)
+	}
+
+	async fn run(&mut self, command: Command) -> Result<Flow> {
+		match command {
+			Command::Help => help(),
+			Command::Send => self.send().await?,
+			Command::Quit => {
+				return Ok(Flow::Quit);
+			}
+			Command::Drop => {
+				let mut savepoint = savepoint(self.pool, &mut self.optional_tx).await?;
+				let result = db::drop(&mut savepoint)
+					.await
+					.wrap_err("Cannot detete the contents of the database");
+				let result = db::end_savepoint(savepoint, result).await?;
+				self.check(result);
+			}
+			Command::Zone(zone) => {
+				self.scope.zone = zone.as_deref().map(db::normalize_name);
+			}
+			Command::Origin(origin) => {
+				self.scope.origin = origin.as_deref().map(db::normalize_name);
+			}
+			Command::List(zone) => self.list(zone).await?,
+			Command::Ttl(ttl) => {
+				self.ttl = ttl;
+			}
+			Command::Class(class) => {
+				self.class = Some(class);
+			}
+			Command::Update(update) => self.update(update).await?,
+			Command::Show => self.show(),
+			Command::Answer => {
+				if let Some(status) = &self.answer {
+					println!("Answer:\n;; ->>HEADER<<- opcode: UPDATE, status: {status}");
+				}
+			}
+			Command::Debug => {
+				self.debug = true;
+			}
+			Command::Version => {
+				println!("zonegen {}", VERSION.unwrap_or("unknown"));
+			}
+			Command::Key { name, secret } => {
+				let result = tsig::Key::from_argument(&format!("{name}:{secret}"));
+				match result {
+					Ok(key) if !self.authorization.is_restricted() => {
+						self.warn(&format!(
+							"the key `{}` is not checked because no allowed keys were given with --keys",
+							key.name()
+						));
+					}
+					Ok(key) => {
+						let result = self.authorization.use_key(key);
+						self.check(result);
+					}
+					Err(e) => self.check(Err(e)),
+				}
+			}
+			Command::Ignored(keyword) => {
+				self.warn(&format!(
+					"Ignoring the {keyword} command, it only applies to sending updates to a DNS server"
+				));
+			}
+			Command::Prereq(prereq) => {
+				let data = match prereq
+					.data
+					.map(|data| self.scope.absolute_data(data))
+					.transpose()
+				{
+					Ok(data) => data,
+					Err(e) => {
+						self.check(Err(e));
+						return Ok(Flow::Continue);
+					}
+				};
+				let prereq = Prereq {
+					class: prereq.class.or_else(|| self.class.clone()),
+					data,
+					..prereq
+				};
+				let mut savepoint = savepoint(self.pool, &mut self.optional_tx).await?;
+				let result = db::resolve_prereq(prereq, &mut savepoint, &self.scope)
+					.await
+					.wrap_err("Cannot add a prerequisite")
+					.map(|prereq| self.prereqs.push(prereq));
+				let result = db::end_savepoint(savepoint, result).await?;
+				self.check(result);
+			}
+		}
+		Ok(Flow::Continue)
+	}
+
+	/// Commit the pending update, unless a prerequisite is not met
+	async fn send(&mut self) -> Result<()> {
+		let has_update = self.optional_tx.is_some() || !self.prereqs.is_empty();
+		if has_update && let Err(e) = self.authorization.check() {
+			// Like a DNS server that refuses an update without a valid key
+			self.prereqs.clear();
+			self.updates.clear();
+			self.answer = Some("REFUSED".to_string());
+			db::optionally_rollback_transaction(self.optional_tx.take()).await?;
+			self.report(&format!("update failed: REFUSED\n{e:#}"));
+			return Ok(());
+		}
+		let rcode = if self.prereqs.is_empty() {
+			None
+		} else {
+			// A separate connection sees the zones as they were before the pending update
+			let mut connection = self
+				.pool
+				.acquire()
+				.await
+				.wrap_err("Cannot acquire a database connection")?;
+			db::check_prereqs(&mut connection, &self.prereqs).await?
+		};
+		self.prereqs.clear();
+		self.updates.clear();
+		self.answer = Some(rcode.map_or_else(|| "NOERROR".to_string(), |rcode| rcode.to_string()));
+		if let Some(rcode) = rcode {
+			if let Some(tx) = self.optional_tx.take() {
+				tx.rollback()
+					.await
+					.wrap_err("Cannot roll back transaction")?;
+			}
+			self.report(&format!("update failed: {rcode}"));
+		} else {
+			self.optional_tx = db::optionally_commit_transaction(self.optional_tx.take()).await?;
+		}
+		Ok(())
+	}
+
+	/// Print the pending update like `nsupdate` does
+	fn show(&self) {
+		println!("Outgoing update query:");
+		if !self.prereqs.is_empty() {
+			println!(";; PREREQUISITE SECTION:");
+			for prereq in &self.prereqs {
+				println!("{prereq}");
+			}
+		}
+		if !self.updates.is_empty() {
+			println!(";; UPDATE SECTION:");
+			for update in &self.updates {
+				println!("{update}");
+			}
+		}
+	}
+
+	async fn list(&mut self, zone: Option<String>) -> Result<()> {
+		let zone = zone.or_else(|| self.scope.zone.clone());
+		// Include the pending update in the listing
+		let result = if let Some(tx) = &mut self.optional_tx {
+			db::list(tx, zone.as_deref()).await
+		} else {
+			let mut connection = self
+				.pool
+				.acquire()
+				.await
+				.wrap_err("Cannot acquire a database connection")?;
+			db::list(&mut connection, zone.as_deref()).await
+		};
+		self.check(result.map(|listing| print!("{listing}")));
+		Ok(())
+	}
+
+	async fn update(&mut self, update: Update) -> Result<()> {
+		// Names in the data are relative to the origin as well, not to the origin of the zone file
+		let update = match update {
+			Update::Add(r) => self
+				.scope
+				.absolute_data(r.data)
+				.map(|data| Update::Add(Add { data, ..r })),
+			Update::Delete(r) => r
+				.data
+				.map(|data| self.scope.absolute_data(data))
+				.transpose()
+				.map(|data| Update::Delete(Delete { data, ..r })),
+		};
+		let update = match update {
+			Ok(update) => update,
+			Err(e) => {
+				self.check(Err(e));
+				return Ok(());
+			}
+		};
+		let mut savepoint = savepoint(self.pool, &mut self.optional_tx).await?;
+		let result = match update {
+			Update::Add(r) => {
+				let r = Add {
+					ttl: r.ttl.or(self.ttl),
+					class: r.class.or_else(|| self.class.clone()),
+					..r
+				};
+				if self.interactive || self.debug {
+					println!("Add request: {r:?}");
+				}
+				let record = self.scope.absolute_name(&r.name).map(|name| {
+					format!(
+						"{name}.\t{}\t{}\t{}\t{}",
+						r.ttl.unwrap_or_default(),
+						r.class.as_deref().unwrap_or("IN"),
+						r.data.record_type(),
+						r.data
+					)
+				});
+				db::add(r, &mut savepoint, &self.scope)
+					.await
+					.wrap_err("Cannot add a record")
+					.and(record)
+			}
+			Update::Delete(r) => {
+				let r = Delete {
+					class: r.class.or_else(|| self.class.clone()),
+					..r
+				};
+				if self.interactive || self.debug {
+					println!("Delete request: {r:?}");
+				}
+				// Deleting RRsets uses the class ANY and deleting single records the class NONE (RFC 2136 section 2.5)
+				let record = self.scope.absolute_name(&r.name).map(|name| {
+					let type_ = r
+						.type_
+						.map_or_else(|| "ANY".to_string(), |type_| type_.to_string());
+					r.data.as_ref().map_or_else(
+						|| format!("{name}.\t0\tANY\t{type_}"),
+						|data| format!("{name}.\t0\tNONE\t{type_}\t{data}"),
+					)
+				});
+				db::delete(r, &mut savepoint, &self.scope)
+					.await
+					.wrap_err("Cannot delete a record")
+					.and(record)
+			}
+		};
+		let result = result.map(|record| self.updates.push(record));
+		let result = db::end_savepoint(savepoint, result).await?;
+		self.check(result);
+		Ok(())
+	}
+
+	/// Print an error message and remember that the session failed
+	fn report(&mut self, message: &str) {
+		self.failed = true;
+		if self.interactive {
+			eprintln!("{message}");
+		} else {
+			// Tell the author of the script which line is wrong
+			eprintln!("line {}: {message}", self.line_number);
+		}
+	}
+
+	/// Print a warning, the session does not fail because of it
+	fn warn(&self, message: &str) {
+		if self.interactive {
+			eprintln!("WARNING: {message}");
+		} else {
+			eprintln!("line {}: WARNING: {message}", self.line_number);
+		}
+	}
+
+	/// Report a failed command, the rest of the pending update is kept
+	fn check(&mut self, result: Result<()>) {
+		if let Err(e) = result {
+			self.report(&format!("{e:#}"));
+		}
+	}
+
+	/// Discard any pending update and report whether every command succeeded
+	async fn finish(self) -> Result<bool> {
+		db::optionally_rollback_transaction(self.optional_tx).await?;
+		Ok(!self.failed)
+	}
+}
+
+fn help() {
+	let version = VERSION.unwrap_or("unknown");
+	printdoc! {"
+		zonegen v{version}
+		send                      (Send the update request)
+		quit                      (Quit, any pending update is not sent)
+		help                      (Display this message)
+		drop                      (Delete the contents of the database)
+		zone [zone]               (Set or clear the zone of the following updates)
+		origin [name]             (Set or clear the origin of relative names)
+		list [zone]               (List the records of the zone or of all zones)
+		ttl value|none            (Set or clear the default TTL of added records)
+		class class               (Set the default class, IN if not set)
+		[update] add ....         (Add the given record to the zone)
+		[update] del[ete] ....    (Remove the given record(s) from the zone)
+		prereq nxdomain name      (Require that the name does not exist)
+		prereq yxdomain name      (Require that the name exists)
+		prereq nxrrset ....       (Require that the RRset does not exist)
+		prereq yxrrset ....       (Require that the RRset exists, with the given data if any)
+		show                      (Show the pending update)
+		answer                    (Show the answer to the last send)
+		debug                     (Print each request)
+		version                   (Print the version)
+		key [hmac:]keyname secret (Use the TSIG key, which has to be one of the keys in --keys)
+		server, local, ...        (Accepted for compatibility with nsupdate but ignored)
+	"};
+}
+
+/// Create a savepoint in the pending transaction, beginning a new transaction if there is none yet.
+/// Each command runs in its own savepoint, so that a failing command does not affect the rest of the pending update.
+async fn savepoint<'a, 'b>(
+	pool: &'a Pool<Sqlite>,
+	optional_tx: &'b mut Option<Transaction<'a, Sqlite>>,
+) -> Result<Transaction<'b, Sqlite>> {
+	*optional_tx = db::optionally_create_transaction(pool, optional_tx.take()).await?;
+	let tx = optional_tx
+		.as_mut()
+		.expect("a transaction should exist here");
+	db::begin_savepoint(tx).await
+}
+
+/// Read commands interactively from the terminal with line editing and history
+pub async fn repl(
+	pool: &Pool<Sqlite>,
+	psl: Option<&PublicSuffixList>,
+	debug: bool,
+	authorization: tsig::Authorization,
+	blank_line_sends: bool,
+) -> Result<bool> {
+	let mut session = Session::new(pool, psl, true, debug);
+	session.authorization = authorization;
+	session.blank_line_sends = blank_line_sends;
 
 	let mut rl = DefaultEditor::new().wrap_err("Cannot create default editor")?;
 	loop {
@@ -27,75 +441,8 @@ pub async fn repl(pool: &Pool<Sqlite>) -> Result<()> {
 			Ok(line) => {
 				rl.add_history_entry(line.as_str())
 					.wrap_err("Cannot add history entry for readline")?;
-				match line.as_str() {
-					"" => {} // Ignore empty inputs
-					non_empty_line => match parse(non_empty_line) {
-						Ok(Command::Help) => {
-							let version = VERSION.unwrap_or("unknown");
-							printdoc! {"
-								zonegen v{version}
-								send                      (Send the update request)
-								quit                      (Quit, any pending update is not sent)
-								help                      (Display this message)
-								drop                      (Delete the contents of the database)
-								[update] add ....         (Add the given record to the zone)
-								[update] del[ete] ....    (Remove the given record(s) from the zone)
-							"};
-						}
-						Ok(Command::Send) => {
-							optional_tx = db::optionally_commit_transaction(optional_tx).await?;
-						}
-						Ok(Command::Quit) => {
-							break;
-						}
-						Ok(Command::Drop) => {
-							optional_tx =
-								db::optionally_create_transaction(pool, optional_tx).await?;
-							let tx = optional_tx
-								.as_mut()
-								.expect("a transaction should exist here");
-							db::drop(tx)
-								.await
-								.wrap_err("Cannot detete the contents of the database")?;
-						}
-						Ok(Command::Update(Add(r))) => {
-							println!("Add request: {r:?}");
-							optional_tx =
-								db::optionally_create_transaction(pool, optional_tx).await?;
-							let tx = optional_tx
-								.as_mut()
-								.expect("a transaction should exist here");
-							db::add(r, tx, &tld_ext)
-								.await
-								.wrap_err("Cannot add a record")?;
-						}
-						Ok(Command::Update(Delete(r))) => {
-							println!("Delete request: {r:?}");
-							optional_tx =
-								db::optionally_create_transaction(pool, optional_tx).await?;
-							let tx = optional_tx
-								.as_mut()
-								.expect("a transaction should exist here");
-							db::delete(r, tx, &tld_ext)
-								.await
-								.wrap_err("Cannot delete a record")?;
-						}
-						Err(e) => {
-							match e {
-								// TODO: somehow use wrap_err_with() here?
-								nom::Err::Failure(f) => {
-									eprintln!("Failure: {f:?}");
-								}
-								nom::Err::Incomplete(f) => {
-									eprintln!("Incomplete: {f:?}");
-								}
-								nom::Err::Error(f) => {
-									// Print a hopefully nicer error message
-									eprintln!("{}", convert_error(non_empty_line, f));
-								}
-							}
-						}
-					},
+				if session.execute(&line).await? == Flow::Quit {
+					break;
 				}
 			}
 			Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
@@ -107,7 +454,288 @@ pub async fn repl(pool: &Pool<Sqlite>) -> Result<()> {
 			}
 		}
 	}
-	db::optionally_rollback_transaction(optional_tx).await?;
 
-	Ok(())
+	session.finish().await
+}
+
+/// Read commands line by line from a script or a pipe, without prompt or line editing
+pub async fn batch(
+	pool: &Pool<Sqlite>,
+	psl: Option<&PublicSuffixList>,
+	debug: bool,
+	authorization: tsig::Authorization,
+	input: impl BufRead,
+) -> Result<bool> {
+	let mut session = Session::new(pool, psl, false, debug);
+	session.authorization = authorization;
+
+	for line in input.lines() {
+		let line = line.wrap_err("Cannot read the next command")?;
+		if session.execute(&line).await? == Flow::Quit {
+			break;
+		}
+	}
+
+	session.finish().await
+}
+
+#[cfg(test)]
+mod test {
+	use super::Session;
+	use crate::db::{self, test::TempDatabase};
+	use crate::tsig;
+
+	async fn run(session: &mut Session<'_>, lines: &[&str]) {
+		for line in lines {
+			session.execute(line).await.expect("Cannot execute line");
+		}
+	}
+
+	#[tokio::test]
+	async fn failed_command_test() {
+		let database = TempDatabase::new("failed-command").await;
+		let pool = &database.pool;
+		let mut session = Session::new(pool, None, false, false);
+		run(
+			&mut session,
+			&[
+				"update add www.example. 300 IN A 192.0.2.1",
+				// Without the public suffix list, the zone of this name is unknown
+				"update add www.example.com. 300 IN A 192.0.2.2",
+				"update add www.example. 300 IN CNAME other.example.",
+				"update add mail.example. 300 IN A 192.0.2.3",
+				"send",
+			],
+		)
+		.await;
+		// The failed commands are reported, but the rest of the update is still sent
+		assert!(session.failed);
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+		let data: Vec<String> = sqlx::query_scalar("SELECT data FROM records ORDER BY data;")
+			.fetch_all(pool)
+			.await
+			.expect("Cannot SELECT records");
+		assert_eq!(data, ["192.0.2.1", "192.0.2.3"]);
+	}
+
+	#[tokio::test]
+	async fn zone_command_test() {
+		let database = TempDatabase::new("zone-command").await;
+		let pool = &database.pool;
+		db::declare_zones(pool, &["example.org".to_string()])
+			.await
+			.expect("Cannot declare zone");
+		let mut session = Session::new(pool, None, false, false);
+		run(
+			&mut session,
+			&[
+				"update add a.dyn.example.org. 300 IN A 192.0.2.1",
+				"send",
+				// The new zone takes over the record that was added to its parent zone
+				"zone dyn.example.org",
+				"update add b.dyn.example.org. 300 IN A 192.0.2.2",
+				"send",
+				"update delete a.dyn.example.org. A",
+				"send",
+			],
+		)
+		.await;
+		assert!(!session.failed);
+		let records: Vec<(String, String)> = sqlx::query_as(
+			"SELECT zones.name, subdomain FROM records JOIN zones ON records.zoneid = zones.id;",
+		)
+		.fetch_all(pool)
+		.await
+		.expect("Cannot SELECT records");
+		assert_eq!(records, [("dyn.example.org".to_string(), "b".to_string())]);
+	}
+
+	#[tokio::test]
+	async fn key_test() {
+		let database = TempDatabase::new("key").await;
+		let pool = &database.pool;
+		let mut session = Session::new(pool, None, false, false);
+		let allowed =
+			tsig::Key::from_argument("hmac-sha256:ddns-key:c2VjcmV0").expect("Invalid key");
+		session.authorization = tsig::Authorization::new(Some(vec![allowed]), None);
+
+		// Without a key, the update is refused
+		run(
+			&mut session,
+			&["update add www.example. 300 IN A 192.0.2.1", "send"],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("REFUSED"));
+		assert!(session.optional_tx.is_none());
+
+		run(
+			&mut session,
+			&[
+				"key hmac-sha256:ddns-key c2VjcmV1",
+				"update add www.example. 300 IN A 192.0.2.2",
+				"send",
+			],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("REFUSED"));
+
+		run(
+			&mut session,
+			&[
+				"key hmac-sha256:ddns-key c2VjcmV0",
+				"update add www.example. 300 IN A 192.0.2.3",
+				"send",
+			],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+		let data: Vec<String> = sqlx::query_scalar("SELECT data FROM records;")
+			.fetch_all(pool)
+			.await
+			.expect("Cannot SELECT records");
+		assert_eq!(data, ["192.0.2.3"]);
+	}
+
+	#[tokio::test]
+	async fn blank_line_test() {
+		let database = TempDatabase::new("blank-line").await;
+		let pool = &database.pool;
+
+		// In a script, a blank line sends the pending update
+		let mut session = Session::new(pool, None, false, false);
+		run(
+			&mut session,
+			&["update add a.example. 300 IN A 192.0.2.1", ""],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+		assert!(session.optional_tx.is_none());
+
+		// A human would be surprised by it
+		let mut session = Session::new(pool, None, true, false);
+		run(
+			&mut session,
+			&["update add b.example. 300 IN A 192.0.2.2", ""],
+		)
+		.await;
+		assert_eq!(session.answer, None);
+		assert!(session.optional_tx.is_some());
+		session.finish().await.expect("Cannot finish session");
+
+		// Unless --blank-line-sends is given
+		let mut session = Session::new(pool, None, true, false);
+		session.blank_line_sends = true;
+		run(
+			&mut session,
+			&["update add c.example. 300 IN A 192.0.2.3", ""],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+
+		let data: Vec<String> = sqlx::query_scalar("SELECT data FROM records ORDER BY data;")
+			.fetch_all(pool)
+			.await
+			.expect("Cannot SELECT records");
+		assert_eq!(data, ["192.0.2.1", "192.0.2.3"]);
+	}
+
+	#[tokio::test]
+	async fn comment_test() {
+		let database = TempDatabase::new("comment").await;
+		let pool = &database.pool;
+		let mut session = Session::new(pool, None, false, false);
+		run(
+			&mut session,
+			&[
+				"; comment like in nsupdate",
+				"# comment like in a shell script",
+				"update add a.example. 300 IN A 192.0.2.1",
+			],
+		)
+		.await;
+		assert!(!session.failed);
+		// Comments are counted, so that errors point to the right line
+		assert_eq!(session.line_number, 3);
+		run(&mut session, &["banana"]).await;
+		assert!(session.failed);
+		assert_eq!(session.line_number, 4);
+	}
+
+	#[tokio::test]
+	async fn indentation_test() {
+		let database = TempDatabase::new("indentation").await;
+		let pool = &database.pool;
+		let mut session = Session::new(pool, None, false, false);
+		run(
+			&mut session,
+			&[
+				"\tupdate add www.example. 300 IN A 192.0.2.1 ",
+				"  update add www.example. 300 IN TXT \"text\"\t",
+				"  ; comment",
+				" \t",
+				"  send  ",
+			],
+		)
+		.await;
+		assert!(!session.failed);
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+		let data: Vec<String> = sqlx::query_scalar("SELECT data FROM records ORDER BY data;")
+			.fetch_all(pool)
+			.await
+			.expect("Cannot SELECT records");
+		assert_eq!(data, ["\"text\"", "192.0.2.1"]);
+	}
+
+	#[tokio::test]
+	async fn send_test() {
+		let database = TempDatabase::new("send").await;
+		let pool = &database.pool;
+		let mut session = Session::new(pool, None, false, false);
+		run(
+			&mut session,
+			&["update add www.example. 300 IN A 192.0.2.1", "send"],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+
+		// The whole update is rolled back if a prerequisite is not met
+		run(
+			&mut session,
+			&[
+				"prereq nxdomain www.example.",
+				"update add new.example. 300 IN A 192.0.2.2",
+				"update delete www.example.",
+				"send",
+			],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("YXDOMAIN"));
+		assert!(session.failed);
+		assert!(session.optional_tx.is_none());
+		let mut connection = pool.acquire().await.expect("Cannot acquire connection");
+		let listing = db::list(&mut connection, None)
+			.await
+			.expect("Cannot list records");
+		assert!(listing.contains("www.example."));
+		assert!(!listing.contains("new.example."));
+		drop(connection);
+
+		// A met prerequisite does not keep the update from being committed, and the next update starts afresh
+		run(
+			&mut session,
+			&[
+				"prereq yxrrset www.example. A 192.0.2.1",
+				"update add new.example. 300 IN A 192.0.2.2",
+				"send",
+			],
+		)
+		.await;
+		assert_eq!(session.answer.as_deref(), Some("NOERROR"));
+		assert!(session.prereqs.is_empty());
+		let data: Vec<String> = sqlx::query_scalar("SELECT data FROM records ORDER BY data;")
+			.fetch_all(pool)
+			.await
+			.expect("Cannot SELECT records");
+		assert_eq!(data, ["192.0.2.1", "192.0.2.2"]);
+	}
 }
@@ -0,0 +1,283 @@
+// SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, tag_no_case, take_until, take_while, take_while1};
+use nom::character::complete::{char, multispace1, not_line_ending};
+use nom::combinator::{all_consuming, value};
+use nom::multi::{many0, many1};
+use nom::sequence::{delimited, preceded};
+use nom::{IResult, Parser};
+use nom_language::error::{VerboseError, convert_error};
+use std::{fs, path::Path};
+
+type Res<T, U> = IResult<T, U, VerboseError<T>>;
+
+/// TSIG key like in the `key` statement of BIND, which is also the format of the key files of nsupdate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+	/// Lowercase without the trailing dot
+	name: String,
+	/// Lowercase, like `hmac-sha256`
+	algorithm: String,
+	/// Base64 encoded
+	secret: String,
+}
+
+impl Key {
+	fn new(name: &str, algorithm: &str, secret: &str) -> Self {
+		let algorithm = algorithm.trim_end_matches('.').to_ascii_lowercase();
+		Self {
+			name: name.trim_end_matches('.').to_ascii_lowercase(),
+			// Old name of HMAC-MD5 (RFC 8945 section 6)
+			algorithm: if algorithm == "hmac-md5.sig-alg.reg.int" {
+				"hmac-md5".to_string()
+			} else {
+				algorithm
+			},
+			secret: secret.to_string(),
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Key given to nsupdate with `-y [hmac:]keyname:secret`, the algorithm defaults to HMAC-MD5 like in nsupdate
+	pub fn from_argument(argument: &str) -> Result<Self> {
+		let parts: Vec<&str> = argument.split(':').collect();
+		match parts.as_slice() {
+			[name, secret] if !name.is_empty() && !secret.is_empty() => {
+				Ok(Self::new(name, "hmac-md5", secret))
+			}
+			[algorithm, name, secret]
+				if !algorithm.is_empty() && !name.is_empty() && !secret.is_empty() =>
+			{
+				Ok(Self::new(name, algorithm, secret))
+			}
+			_ => bail!("The key `{argument}` is not of the form [hmac:]keyname:secret"),
+		}
+	}
+
+	/// First key of a key file given to nsupdate with `-k`
+	pub fn from_file(path: &Path) -> Result<Self> {
+		load(path)?
+			.into_iter()
+			.next()
+			.ok_or_else(|| eyre!("The key file `{}` does not contain a key", path.display()))
+	}
+
+	/// Compare in constant time so that the secret cannot be guessed from the time it takes
+	fn matches(&self, other: &Self) -> bool {
+		let secret_matches = self.secret.len() == other.secret.len()
+			&& self
+				.secret
+				.bytes()
+				.zip(other.secret.bytes())
+				.fold(0, |difference, (a, b)| difference | (a ^ b))
+				== 0;
+		self.name == other.name && self.algorithm == other.algorithm && secret_matches
+	}
+}
+
+/// All keys in a file with `key` statements like the ones written by `tsig-keygen`
+pub fn load(path: &Path) -> Result<Vec<Key>> {
+	let contents = fs::read_to_string(path)
+		.wrap_err_with(|| format!("Cannot read key file `{}`", path.display()))?;
+	parse(&contents).wrap_err_with(|| format!("Cannot parse key file `{}`", path.display()))
+}
+
+/// Only the key that is allowed to make changes may be used
+pub fn authorize(allowed_keys: &[Key], key: Option<&Key>) -> Result<()> {
+	let Some(key) = key else {
+		bail!(
+			"A key has to be given with -k, -y or the key command because only the keys in --keys are allowed"
+		);
+	};
+	if !allowed_keys.iter().any(|allowed| allowed.matches(key)) {
+		bail!(
+			"The key `{}` is not allowed, its name, algorithm and secret have to match a key in --keys",
+			key.name
+		);
+	}
+	Ok(())
+}
+
+/// Keys that are allowed to make changes, if they are restricted, and the key that is used for the next update.
+/// Like in nsupdate, the key given with -k or -y can be replaced with the `key` command.
+pub struct Authorization {
+	allowed_keys: Option<Vec<Key>>,
+	key: Option<Key>,
+}
+
+impl Authorization {
+	pub const fn unrestricted() -> Self {
+		Self {
+			allowed_keys: None,
+			key: None,
+		}
+	}
+
+	pub const fn new(allowed_keys: Option<Vec<Key>>, key: Option<Key>) -> Self {
+		Self { allowed_keys, key }
+	}
+
+	pub const fn is_restricted(&self) -> bool {
+		self.allowed_keys.is_some()
+	}
+
+	/// Use another key for the next updates, it is checked right away so that a wrong key is noticed early
+	pub fn use_key(&mut self, key: Key) -> Result<()> {
+		self.key = Some(key);
+		self.check()
+	}
+
+	/// Whether the key that is used may make changes
+	pub fn check(&self) -> Result<()> {
+		self.allowed_keys.as_ref().map_or(Ok(()), |allowed_keys| {
+			authorize(allowed_keys, self.key.as_ref())
+		})
+	}
+}
+
+fn parse(input: &str) -> Result<Vec<Key>> {
+	let (_, keys) = all_consuming(many0(key))
+		.parse(input)
+		.map_err(|e| match e {
+			nom::Err::Error(e) | nom::Err::Failure(e) => eyre!("{}", convert_error(input, e)),
+			nom::Err::Incomplete(_) => eyre!("Incomplete input"),
+		})?;
+	keys.into_iter().collect()
+}
+
+/// Whitespace and `#`, `//` and `/* */` comments like in the configuration of BIND, but not inside of quoted strings
+fn blank(input: &str) -> Res<&str, ()> {
+	value(
+		(),
+		many0(alt((
+			value((), multispace1),
+			value((), preceded(alt((tag("#"), tag("//"))), not_line_ending)),
+			value((), (tag("/*"), take_until("*/"), tag("*/"))),
+		))),
+	)
+	.parse(input)
+}
+
+fn string(input: &str) -> Res<&str, &str> {
+	delimited(
+		blank,
+		alt((
+			delimited(char('"'), take_while(|c| c != '"'), char('"')),
+			is_not(" \t\r\n{};\"#/"),
+		)),
+		blank,
+	)
+	.parse(input)
+}
+
+fn clause(input: &str) -> Res<&str, (&str, &str)> {
+	(string, string, delimited(blank, char(';'), blank))
+		.parse(input)
+		.map(|(next_input, (keyword, value, _))| (next_input, (keyword, value)))
+}
+
+fn key(input: &str) -> Res<&str, Result<Key>> {
+	(
+		delimited(blank, tag_no_case("key"), take_while1(char::is_whitespace)),
+		string,
+		delimited(blank, char('{'), blank),
+		many1(clause),
+		delimited(blank, char('}'), blank),
+		delimited(blank, char(';'), blank),
+	)
+		.parse(input)
+		.map(|(next_input, (_, name, _, clauses, _, _))| {
+			let value = |keyword: &str| {
+				clauses
+					.iter()
+					.find(|(clause, _)| clause.eq_ignore_ascii_case(keyword))
+					.map(|(_, value)| *value)
+					.ok_or_else(|| eyre!("The key `{name}` has no {keyword}"))
+			};
+			let key = value("algorithm")
+				.and_then(|algorithm| Ok(Key::new(name, algorithm, value("secret")?)));
+			(next_input, key)
+		})
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Authorization, Key, authorize, parse};
+	use indoc::indoc;
+
+	#[test]
+	fn parse_test() {
+		let keys = parse(indoc! {r#"
+			# Written by tsig-keygen
+			key "ddns-key.example.org" {
+				algorithm hmac-sha256;
+				secret "c2VjcmV0";
+			};
+			key Other. { algorithm HMAC-MD5.SIG-ALG.REG.INT; secret "b3RoZXI="; }; // second key
+			/* A secret can contain what looks like a comment */
+			key "slashes" {
+				algorithm hmac-sha256; # comment
+				secret "ab//cdEF#gh/*ij";
+			};
+		"#});
+		assert_eq!(
+			keys.ok(),
+			Some(vec![
+				Key::new("ddns-key.example.org", "hmac-sha256", "c2VjcmV0"),
+				Key::new("other", "hmac-md5", "b3RoZXI="),
+				Key::new("slashes", "hmac-sha256", "ab//cdEF#gh/*ij"),
+			])
+		);
+		assert!(parse(r#"key "a" { algorithm hmac-sha256; };"#).is_err());
+		assert!(parse(r#"key "a" { algorithm hmac-sha256; secret "x" };"#).is_err());
+	}
+
+	#[test]
+	fn argument_test() {
+		assert_eq!(
+			Key::from_argument("hmac-sha256:ddns-key.example.org:c2VjcmV0").ok(),
+			Some(Key::new("ddns-key.example.org", "hmac-sha256", "c2VjcmV0"))
+		);
+		assert_eq!(
+			Key::from_argument("ddns-key.example.org:c2VjcmV0").ok(),
+			Some(Key::new("ddns-key.example.org", "hmac-md5", "c2VjcmV0"))
+		);
+		assert!(Key::from_argument("c2VjcmV0").is_err());
+	}
+
+	#[test]
+	fn authorize_test() {
+		let allowed = [Key::new("ddns-key.example.org", "hmac-sha256", "c2VjcmV0")];
+		let allows = |name, algorithm, secret| {
+			authorize(&allowed, Some(&Key::new(name, algorithm, secret))).is_ok()
+		};
+		assert!(allows("DDNS-key.example.org.", "hmac-sha256", "c2VjcmV0"));
+		assert!(!allows("ddns-key.example.org", "hmac-sha256", "c2VjcmV1"));
+		assert!(!allows("ddns-key.example.org", "hmac-sha512", "c2VjcmV0"));
+		assert!(!allows("other", "hmac-sha256", "c2VjcmV0"));
+		assert!(authorize(&allowed, None).is_err());
+	}
+
+	#[test]
+	fn authorization_test() {
+		let allowed = Key::new("ddns-key.example.org", "hmac-sha256", "c2VjcmV0");
+		let wrong = Key::new("ddns-key.example.org", "hmac-sha256", "c2VjcmV1");
+		let mut authorization = Authorization::new(Some(vec![allowed.clone()]), None);
+		assert!(authorization.check().is_err());
+		assert!(authorization.use_key(allowed).is_ok());
+		assert!(authorization.check().is_ok());
+		// The last key counts
+		assert!(authorization.use_key(wrong.clone()).is_err());
+		assert!(authorization.check().is_err());
+
+		let mut authorization = Authorization::unrestricted();
+		assert!(authorization.check().is_ok());
+		assert!(authorization.use_key(wrong).is_ok());
+	}
+}
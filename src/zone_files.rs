@@ -1,9 +1,49 @@
 // SPDX-FileCopyrightText: 2024 Luflosi <zonegen@luflosi.de>
 // SPDX-License-Identifier: GPL-3.0-only
 
+use crate::record::RData;
 use atomic_write_file::{AtomicWriteFile, unix::OpenOptionsExt as AtomicOpenOptionsExt};
 use color_eyre::eyre::{Result, WrapErr};
-use std::{fs, io::Write, os::unix::fs::OpenOptionsExt as UnixOpenOptionsExt, path::Path};
+use std::{
+	fmt::Write as FmtWrite, fs, io::Write, os::unix::fs::OpenOptionsExt as UnixOpenOptionsExt,
+	path::Path,
+};
+
+/// Present a <character-string> quoted and with escapes where necessary, see RFC 1035 section 5.1
+pub fn quote_character_string(bytes: &[u8]) -> String {
+	let mut quoted = String::from("\"");
+	for &byte in bytes {
+		match byte {
+			b'"' | b'\\' => {
+				quoted.push('\\');
+				quoted.push(char::from(byte));
+			}
+			b' '..=b'~' => quoted.push(char::from(byte)),
+			_ => write!(quoted, "\\{byte:03}").expect("writing to a String should never fail"),
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+/// Line of a zone file for a single record
+pub fn record_line(subdomain: &str, ttl: i64, class: &str, rdata: &RData) -> String {
+	format!(
+		"{subdomain: <20} {ttl: >6} {class: <3} {: <5} {rdata}\n",
+		rdata.record_type()
+	)
+}
+
+/// Line of a zone file for a record of a type that zonegen does not know, with the data as it was stored
+pub fn verbatim_record_line(
+	subdomain: &str,
+	ttl: i64,
+	class: &str,
+	type_: &str,
+	data: &str,
+) -> String {
+	format!("{subdomain: <20} {ttl: >6} {class: <3} {type_: <5} {data}\n")
+}
 
 pub fn save(zone_name: &str, zone_data: &str, dir: &Path) -> Result<()> {
 	let zone_file_name = format!("{zone_name}.zone");